@@ -0,0 +1,226 @@
+//! Verification of files against a checksum manifest (`--check`).
+
+use crate::color;
+use crate::encoding::Encoding;
+use crate::hasher::{self, Algorithm, Options};
+use crate::manifest::{self, Entry};
+use colored::*;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Pick the algorithm for a digest of `len` bytes, preferring the order
+/// of `candidates`. Every algorithm's usual length is tried first, so
+/// `--length` only decides between variable-length algorithms when no
+/// fixed-length one fits.
+fn algorithm_for(len: usize, candidates: &[Algorithm], options: &Options) -> Option<Algorithm> {
+    [&Options::default(), options]
+        .into_iter()
+        .find_map(|options| {
+            candidates
+                .iter()
+                .copied()
+                .find(|a| a.output_len(options) == len)
+        })
+}
+
+/// Shortest digest accepted for a variable-length algorithm unless
+/// `--length` asks for it, so a truncated digest can't stand in for a
+/// whole one
+const MIN_VARIABLE_LEN: usize = 16;
+
+/// Work out the algorithm and options to verify `entry` with, or `None`
+/// if the line can't be checked.
+fn prepare_entry(
+    entry: &Entry,
+    candidates: &[Algorithm],
+    options: &Options,
+) -> Option<(Algorithm, Options)> {
+    let algorithm = entry
+        .algorithm
+        .or_else(|| algorithm_for(entry.digest.len(), candidates, options))?;
+
+    // Variable-length algorithms produce as much output as was recorded
+    let mut entry_options = options.clone();
+    if algorithm.is_variable_length() {
+        let bits = entry.digest.len() * 8;
+        match options.length {
+            Some(length) if length != bits => return None,
+            None if entry.digest.len() < MIN_VARIABLE_LEN => return None,
+            _ => {}
+        }
+        entry_options.length = Some(bits);
+    }
+    // BSD lines say whether they hold an HMAC; others follow --hmac-key
+    if entry.algorithm.is_some() && !entry.hmac {
+        entry_options.hmac_key = None;
+    }
+    if entry.hmac && entry_options.hmac_key.is_none() {
+        return None;
+    }
+    hasher::validate_algorithm(algorithm, &entry_options).ok()?;
+    Some((algorithm, entry_options))
+}
+
+/// Check every file listed in `manifest` (or stdin for `-`), whose digests
+/// are written in `encoding`.
+///
+/// Returns `Ok(true)` if every listed file was present and matched.
+pub fn check_manifest(
+    manifest: &Path,
+    candidates: &[Algorithm],
+    options: &Options,
+    encoding: Encoding,
+) -> io::Result<bool> {
+    let contents = if manifest == Path::new("-") {
+        let mut buffer = String::new();
+        io::stdin().read_to_string(&mut buffer)?;
+        buffer
+    } else {
+        fs::read_to_string(manifest)?
+    };
+
+    let mut checked = 0;
+    let mut failed = 0;
+    let mut missing = 0;
+    let mut malformed = 0;
+
+    for line in contents.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((entry, (algorithm, entry_options))) = manifest::parse_line(line, encoding)
+            .and_then(|e| {
+                let prepared = prepare_entry(&e, candidates, options)?;
+                Some((e, prepared))
+            })
+        else {
+            malformed += 1;
+            continue;
+        };
+        checked += 1;
+
+        let file = match File::open(&entry.path) {
+            Ok(file) => file,
+            Err(_) => {
+                println!("{}: {}", entry.path, "MISSING".yellow().bold());
+                missing += 1;
+                continue;
+            }
+        };
+        match hasher::hash_reader(file, &[algorithm], &entry_options) {
+            Ok((_, digests)) if digests[0].1 == entry.digest => {
+                println!("{}: {}", entry.path, "OK".green().bold());
+            }
+            Ok(_) => {
+                println!("{}: {}", entry.path, "FAILED".red().bold());
+                failed += 1;
+            }
+            Err(e) => {
+                println!("{}: {} ({})", entry.path, "FAILED".red().bold(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    if malformed > 0 {
+        eprintln!(
+            "{}: {} line(s) are improperly formatted",
+            color::stderr("Warning".yellow().bold()),
+            malformed
+        );
+    }
+    if checked == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no properly formatted checksum lines found",
+        ));
+    }
+    if missing > 0 {
+        eprintln!(
+            "{}: {} listed file(s) could not be read",
+            color::stderr("Warning".yellow().bold()),
+            missing
+        );
+    }
+    if failed > 0 {
+        eprintln!(
+            "{}: {} computed checksum(s) did NOT match",
+            color::stderr("Warning".yellow().bold()),
+            failed
+        );
+    }
+
+    Ok(failed == 0 && missing == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(algorithm: Algorithm, digest: &[u8]) -> Entry {
+        Entry {
+            algorithm: Some(algorithm),
+            hmac: false,
+            digest: digest.to_vec(),
+            path: "a.txt".to_string(),
+        }
+    }
+
+    #[test]
+    fn untagged_lines_are_guessed_from_the_usual_lengths() {
+        let options = Options {
+            length: Some(128),
+            ..Options::default()
+        };
+        let all = Algorithm::ALL;
+        assert_eq!(algorithm_for(16, &all, &options), Some(Algorithm::Md5));
+        let shake = [Algorithm::Shake128];
+        assert_eq!(
+            algorithm_for(16, &shake, &options),
+            Some(Algorithm::Shake128)
+        );
+        assert_eq!(
+            algorithm_for(32, &shake, &options),
+            Some(Algorithm::Shake128)
+        );
+        assert_eq!(algorithm_for(20, &shake, &options), None);
+    }
+
+    #[test]
+    fn truncated_variable_length_digests_are_rejected() {
+        let options = Options::default();
+        for algorithm in [
+            Algorithm::Shake128,
+            Algorithm::Shake256,
+            Algorithm::Blake2b,
+            Algorithm::Blake2s,
+            Algorithm::Blake3,
+        ] {
+            for len in [0, 1, MIN_VARIABLE_LEN - 1] {
+                let entry = entry(algorithm, &vec![0; len]);
+                assert!(prepare_entry(&entry, &[], &options).is_none());
+            }
+            let entry = entry(algorithm, &[0; MIN_VARIABLE_LEN]);
+            let (_, entry_options) = prepare_entry(&entry, &[], &options).unwrap();
+            assert_eq!(entry_options.length, Some(MIN_VARIABLE_LEN * 8));
+        }
+    }
+
+    #[test]
+    fn short_variable_length_digests_need_a_matching_length() {
+        let short = entry(Algorithm::Shake128, &[0; 4]);
+        let options = Options {
+            length: Some(32),
+            ..Options::default()
+        };
+        assert!(prepare_entry(&short, &[], &options).is_some());
+        let options = Options {
+            length: Some(64),
+            ..Options::default()
+        };
+        assert!(prepare_entry(&short, &[], &options).is_none());
+        assert!(prepare_entry(&entry(Algorithm::Shake128, &[0; 32]), &[], &options).is_none());
+    }
+}
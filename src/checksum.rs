@@ -0,0 +1,308 @@
+//! Non-cryptographic checksums: the CRC catalogue and Adler-32.
+
+use crate::hasher::Hasher;
+
+/// Parameters of a CRC in the Rocksoft model, as listed in the catalogue at
+/// <https://reveng.sourceforge.io/crc-catalogue/>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CrcPreset {
+    pub name: &'static str,
+    pub width: u8,
+    pub poly: u64,
+    pub init: u64,
+    pub refin: bool,
+    pub refout: bool,
+    pub xorout: u64,
+}
+
+macro_rules! preset {
+    ($konst:ident => $name:literal) => {
+        CrcPreset {
+            name: $name,
+            width: crc_catalog::$konst.width,
+            poly: crc_catalog::$konst.poly as u64,
+            init: crc_catalog::$konst.init as u64,
+            refin: crc_catalog::$konst.refin,
+            refout: crc_catalog::$konst.refout,
+            xorout: crc_catalog::$konst.xorout as u64,
+        }
+    };
+}
+
+macro_rules! presets {
+    ($($konst:ident => $name:literal,)*) => {
+        /// Every catalogue CRC up to 64 bits wide
+        pub const PRESETS: &[CrcPreset] = &[$(preset!($konst => $name)),*];
+
+        /// The catalogue's CRC of "123456789" for each of [`PRESETS`]
+        #[cfg(test)]
+        const CHECKS: &[u64] = &[$(crc_catalog::$konst.check as u64),*];
+    };
+}
+
+/// The CRC used by ZIP, gzip, PNG and Ethernet
+pub const CRC_32: CrcPreset = preset!(CRC_32_ISO_HDLC => "CRC-32/ISO-HDLC");
+/// Castagnoli CRC used by iSCSI, ext4 and SCTP
+pub const CRC_32C: CrcPreset = preset!(CRC_32_ISCSI => "CRC-32/ISCSI");
+/// The CRC used by xz
+pub const CRC_64: CrcPreset = preset!(CRC_64_XZ => "CRC-64/XZ");
+
+presets! {
+    CRC_3_GSM => "CRC-3/GSM",
+    CRC_3_ROHC => "CRC-3/ROHC",
+    CRC_4_G_704 => "CRC-4/G-704",
+    CRC_4_INTERLAKEN => "CRC-4/INTERLAKEN",
+    CRC_5_EPC_C1G2 => "CRC-5/EPC-C1G2",
+    CRC_5_G_704 => "CRC-5/G-704",
+    CRC_5_USB => "CRC-5/USB",
+    CRC_6_CDMA2000_A => "CRC-6/CDMA2000-A",
+    CRC_6_CDMA2000_B => "CRC-6/CDMA2000-B",
+    CRC_6_DARC => "CRC-6/DARC",
+    CRC_6_G_704 => "CRC-6/G-704",
+    CRC_6_GSM => "CRC-6/GSM",
+    CRC_7_MMC => "CRC-7/MMC",
+    CRC_7_ROHC => "CRC-7/ROHC",
+    CRC_7_UMTS => "CRC-7/UMTS",
+    CRC_8_AUTOSAR => "CRC-8/AUTOSAR",
+    CRC_8_BLUETOOTH => "CRC-8/BLUETOOTH",
+    CRC_8_CDMA2000 => "CRC-8/CDMA2000",
+    CRC_8_DARC => "CRC-8/DARC",
+    CRC_8_DVB_S2 => "CRC-8/DVB-S2",
+    CRC_8_GSM_A => "CRC-8/GSM-A",
+    CRC_8_GSM_B => "CRC-8/GSM-B",
+    CRC_8_HITAG => "CRC-8/HITAG",
+    CRC_8_I_432_1 => "CRC-8/I-432-1",
+    CRC_8_I_CODE => "CRC-8/I-CODE",
+    CRC_8_LTE => "CRC-8/LTE",
+    CRC_8_MAXIM_DOW => "CRC-8/MAXIM-DOW",
+    CRC_8_MIFARE_MAD => "CRC-8/MIFARE-MAD",
+    CRC_8_NRSC_5 => "CRC-8/NRSC-5",
+    CRC_8_OPENSAFETY => "CRC-8/OPENSAFETY",
+    CRC_8_ROHC => "CRC-8/ROHC",
+    CRC_8_SAE_J1850 => "CRC-8/SAE-J1850",
+    CRC_8_SMBUS => "CRC-8/SMBUS",
+    CRC_8_TECH_3250 => "CRC-8/TECH-3250",
+    CRC_8_WCDMA => "CRC-8/WCDMA",
+    CRC_10_ATM => "CRC-10/ATM",
+    CRC_10_CDMA2000 => "CRC-10/CDMA2000",
+    CRC_10_GSM => "CRC-10/GSM",
+    CRC_11_FLEXRAY => "CRC-11/FLEXRAY",
+    CRC_11_UMTS => "CRC-11/UMTS",
+    CRC_12_CDMA2000 => "CRC-12/CDMA2000",
+    CRC_12_DECT => "CRC-12/DECT",
+    CRC_12_GSM => "CRC-12/GSM",
+    CRC_12_UMTS => "CRC-12/UMTS",
+    CRC_13_BBC => "CRC-13/BBC",
+    CRC_14_DARC => "CRC-14/DARC",
+    CRC_14_GSM => "CRC-14/GSM",
+    CRC_15_CAN => "CRC-15/CAN",
+    CRC_15_MPT1327 => "CRC-15/MPT1327",
+    CRC_16_ARC => "CRC-16/ARC",
+    CRC_16_CDMA2000 => "CRC-16/CDMA2000",
+    CRC_16_CMS => "CRC-16/CMS",
+    CRC_16_DDS_110 => "CRC-16/DDS-110",
+    CRC_16_DECT_R => "CRC-16/DECT-R",
+    CRC_16_DECT_X => "CRC-16/DECT-X",
+    CRC_16_DNP => "CRC-16/DNP",
+    CRC_16_EN_13757 => "CRC-16/EN-13757",
+    CRC_16_GENIBUS => "CRC-16/GENIBUS",
+    CRC_16_GSM => "CRC-16/GSM",
+    CRC_16_IBM_3740 => "CRC-16/IBM-3740",
+    CRC_16_IBM_SDLC => "CRC-16/IBM-SDLC",
+    CRC_16_ISO_IEC_14443_3_A => "CRC-16/ISO-IEC-14443-3-A",
+    CRC_16_KERMIT => "CRC-16/KERMIT",
+    CRC_16_LJ1200 => "CRC-16/LJ1200",
+    CRC_16_M17 => "CRC-16/M17",
+    CRC_16_MAXIM_DOW => "CRC-16/MAXIM-DOW",
+    CRC_16_MCRF4XX => "CRC-16/MCRF4XX",
+    CRC_16_MODBUS => "CRC-16/MODBUS",
+    CRC_16_NRSC_5 => "CRC-16/NRSC-5",
+    CRC_16_OPENSAFETY_A => "CRC-16/OPENSAFETY-A",
+    CRC_16_OPENSAFETY_B => "CRC-16/OPENSAFETY-B",
+    CRC_16_PROFIBUS => "CRC-16/PROFIBUS",
+    CRC_16_RIELLO => "CRC-16/RIELLO",
+    CRC_16_SPI_FUJITSU => "CRC-16/SPI-FUJITSU",
+    CRC_16_T10_DIF => "CRC-16/T10-DIF",
+    CRC_16_TELEDISK => "CRC-16/TELEDISK",
+    CRC_16_TMS37157 => "CRC-16/TMS37157",
+    CRC_16_UMTS => "CRC-16/UMTS",
+    CRC_16_USB => "CRC-16/USB",
+    CRC_16_XMODEM => "CRC-16/XMODEM",
+    CRC_17_CAN_FD => "CRC-17/CAN-FD",
+    CRC_21_CAN_FD => "CRC-21/CAN-FD",
+    CRC_24_BLE => "CRC-24/BLE",
+    CRC_24_FLEXRAY_A => "CRC-24/FLEXRAY-A",
+    CRC_24_FLEXRAY_B => "CRC-24/FLEXRAY-B",
+    CRC_24_INTERLAKEN => "CRC-24/INTERLAKEN",
+    CRC_24_LTE_A => "CRC-24/LTE-A",
+    CRC_24_LTE_B => "CRC-24/LTE-B",
+    CRC_24_OPENPGP => "CRC-24/OPENPGP",
+    CRC_24_OS_9 => "CRC-24/OS-9",
+    CRC_30_CDMA => "CRC-30/CDMA",
+    CRC_31_PHILIPS => "CRC-31/PHILIPS",
+    CRC_32_AIXM => "CRC-32/AIXM",
+    CRC_32_AUTOSAR => "CRC-32/AUTOSAR",
+    CRC_32_BASE91_D => "CRC-32/BASE91-D",
+    CRC_32_BZIP2 => "CRC-32/BZIP2",
+    CRC_32_CD_ROM_EDC => "CRC-32/CD-ROM-EDC",
+    CRC_32_CKSUM => "CRC-32/CKSUM",
+    CRC_32_ISCSI => "CRC-32/ISCSI",
+    CRC_32_ISO_HDLC => "CRC-32/ISO-HDLC",
+    CRC_32_JAMCRC => "CRC-32/JAMCRC",
+    CRC_32_MEF => "CRC-32/MEF",
+    CRC_32_MPEG_2 => "CRC-32/MPEG-2",
+    CRC_32_XFER => "CRC-32/XFER",
+    CRC_40_GSM => "CRC-40/GSM",
+    CRC_64_ECMA_182 => "CRC-64/ECMA-182",
+    CRC_64_GO_ISO => "CRC-64/GO-ISO",
+    CRC_64_MS => "CRC-64/MS",
+    CRC_64_NVME => "CRC-64/NVME",
+    CRC_64_REDIS => "CRC-64/REDIS",
+    CRC_64_WE => "CRC-64/WE",
+    CRC_64_XZ => "CRC-64/XZ",
+}
+
+/// Look up a catalogue CRC by its (case-insensitive) name
+pub fn preset(name: &str) -> Option<&'static CrcPreset> {
+    PRESETS.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+fn reflect(value: u64, width: u8) -> u64 {
+    value.reverse_bits() >> (64 - width)
+}
+
+/// Table-driven CRC of any width up to 64 bits.
+///
+/// Reflected CRCs keep the register in its reflected form and shift right;
+/// the others keep it aligned to the top of a `u64` and shift left, which
+/// also handles widths below eight bits.
+pub struct Crc {
+    preset: CrcPreset,
+    table: [u64; 256],
+    register: u64,
+}
+
+impl Crc {
+    pub fn new(preset: &CrcPreset) -> Self {
+        let width = preset.width;
+        let mut table = [0u64; 256];
+        if preset.refin {
+            let poly = reflect(preset.poly, width);
+            for (i, entry) in table.iter_mut().enumerate() {
+                let mut value = i as u64;
+                for _ in 0..8 {
+                    value = if value & 1 != 0 {
+                        (value >> 1) ^ poly
+                    } else {
+                        value >> 1
+                    };
+                }
+                *entry = value;
+            }
+        } else {
+            let poly = preset.poly << (64 - width);
+            for (i, entry) in table.iter_mut().enumerate() {
+                let mut value = (i as u64) << 56;
+                for _ in 0..8 {
+                    value = if value & (1 << 63) != 0 {
+                        (value << 1) ^ poly
+                    } else {
+                        value << 1
+                    };
+                }
+                *entry = value;
+            }
+        }
+
+        let register = if preset.refin {
+            reflect(preset.init, width)
+        } else {
+            preset.init << (64 - width)
+        };
+        Crc {
+            preset: *preset,
+            table,
+            register,
+        }
+    }
+
+    pub fn value(&self) -> u64 {
+        let width = self.preset.width;
+        let register = if self.preset.refin {
+            self.register
+        } else {
+            self.register >> (64 - width)
+        };
+        let value = if self.preset.refin != self.preset.refout {
+            reflect(register, width)
+        } else {
+            register
+        };
+        let mask = u64::MAX >> (64 - width);
+        (value ^ self.preset.xorout) & mask
+    }
+}
+
+impl Hasher for Crc {
+    fn update(&mut self, data: &[u8]) {
+        if self.preset.refin {
+            for &byte in data {
+                let index = (self.register ^ byte as u64) & 0xff;
+                self.register = self.table[index as usize] ^ (self.register >> 8);
+            }
+        } else {
+            for &byte in data {
+                let index = ((self.register >> 56) ^ byte as u64) & 0xff;
+                self.register = self.table[index as usize] ^ (self.register << 8);
+            }
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        let bytes = (self.preset.width as usize).div_ceil(8);
+        self.value().to_be_bytes()[8 - bytes..].to_vec()
+    }
+}
+
+#[derive(Default)]
+pub struct Adler32(adler::Adler32);
+
+impl Hasher for Adler32 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.write_slice(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.checksum().to_be_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_match_catalogue_check_values() {
+        assert_eq!(PRESETS.len(), CHECKS.len());
+        for (preset, &check) in PRESETS.iter().zip(CHECKS) {
+            let mut crc = Crc::new(preset);
+            crc.update(b"123456789");
+            assert_eq!(crc.value(), check, "{}", preset.name);
+
+            // The same value when fed in pieces
+            let mut crc = Crc::new(preset);
+            for chunk in b"123456789".chunks(2) {
+                crc.update(chunk);
+            }
+            assert_eq!(crc.value(), check, "{} in chunks", preset.name);
+        }
+    }
+
+    #[test]
+    fn digests_are_as_wide_as_the_crc() {
+        for (name, len) in [("CRC-3/GSM", 1), ("CRC-15/CAN", 2), ("CRC-40/GSM", 5)] {
+            let crc = Box::new(Crc::new(preset(name).unwrap()));
+            assert_eq!(crc.finalize().len(), len, "{}", name);
+        }
+    }
+}
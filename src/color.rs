@@ -0,0 +1,61 @@
+//! Deciding when to colour output on stdout and stderr.
+
+use clap::ValueEnum;
+use colored::{ColoredString, Styles};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Colour when writing to a terminal, honouring NO_COLOR and CLICOLOR_FORCE
+    Auto,
+    Always,
+    Never,
+}
+
+/// Whether stderr gets colour; stdout is handled by `colored` itself
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+fn enabled(choice: ColorChoice, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            // https://no-color.org and https://bixense.com/clicolors
+            if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                return false;
+            }
+            if env::var_os("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0") {
+                return true;
+            }
+            is_terminal
+        }
+    }
+}
+
+/// Decide colour for stdout and stderr separately, so that piping one of
+/// them to a file doesn't strip colour from the other.
+pub fn init(choice: ColorChoice) {
+    colored::control::set_override(enabled(choice, io::stdout().is_terminal()));
+    STDERR_COLOR.store(
+        enabled(choice, io::stderr().is_terminal()),
+        Ordering::Relaxed,
+    );
+}
+
+/// Render `text` for stderr, using stderr's colour setting rather than the
+/// global one `colored` applies to stdout.
+pub fn stderr(text: ColoredString) -> String {
+    if !STDERR_COLOR.load(Ordering::Relaxed) || text.is_plain() {
+        return (*text).to_string();
+    }
+    let mut codes = Vec::new();
+    if text.style().contains(Styles::Bold) {
+        codes.push("1".into());
+    }
+    if let Some(color) = text.fgcolor() {
+        codes.push(color.to_fg_str());
+    }
+    format!("\x1b[{}m{}\x1b[0m", codes.join(";"), &*text)
+}
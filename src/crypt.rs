@@ -0,0 +1,443 @@
+//! Unix crypt(3) hashes for the `crypt` subcommand: md5-crypt (`$1$`),
+//! sha256-crypt (`$5$`) and sha512-crypt (`$6$`), as found in
+//! `/etc/shadow`.
+//!
+//! The SHA variants follow Ulrich Drepper's specification, including the
+//! `rounds=` parameter; md5-crypt is the original FreeBSD algorithm.
+
+use crate::kdf;
+use clap::ValueEnum;
+use colored::*;
+use md5::Md5;
+use password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
+
+/// The alphabet crypt(3) encodes salts and hashes with
+const ALPHABET: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const DEFAULT_ROUNDS: u32 = 5000;
+const MIN_ROUNDS: u32 = 1000;
+const MAX_ROUNDS: u32 = 999_999_999;
+
+/// Byte order in which each digest is encoded, three bytes at a time
+/// (most significant first); a trailing group may repeat indices that are
+/// then not encoded.
+const MD5_ORDER: [[usize; 3]; 6] = [
+    [0, 6, 12],
+    [1, 7, 13],
+    [2, 8, 14],
+    [3, 9, 15],
+    [4, 10, 5],
+    [11, 11, 11],
+];
+const SHA256_ORDER: [[usize; 3]; 11] = [
+    [0, 10, 20],
+    [21, 1, 11],
+    [12, 22, 2],
+    [3, 13, 23],
+    [24, 4, 14],
+    [15, 25, 5],
+    [6, 16, 26],
+    [27, 7, 17],
+    [18, 28, 8],
+    [9, 19, 29],
+    [31, 30, 30],
+];
+const SHA512_ORDER: [[usize; 3]; 22] = [
+    [0, 21, 42],
+    [22, 43, 1],
+    [44, 2, 23],
+    [3, 24, 45],
+    [25, 46, 4],
+    [47, 5, 26],
+    [6, 27, 48],
+    [28, 49, 7],
+    [50, 8, 29],
+    [9, 30, 51],
+    [31, 52, 10],
+    [53, 11, 32],
+    [12, 33, 54],
+    [34, 55, 13],
+    [56, 14, 35],
+    [15, 36, 57],
+    [37, 58, 16],
+    [59, 17, 38],
+    [18, 39, 60],
+    [40, 61, 19],
+    [62, 20, 41],
+    [63, 63, 63],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// md5-crypt, `$1$`
+    Md5,
+    /// sha256-crypt, `$5$`
+    Sha256,
+    /// sha512-crypt, `$6$`
+    Sha512,
+}
+
+impl Method {
+    fn prefix(self) -> &'static str {
+        match self {
+            Method::Md5 => "$1$",
+            Method::Sha256 => "$5$",
+            Method::Sha512 => "$6$",
+        }
+    }
+
+    fn max_salt_len(self) -> usize {
+        match self {
+            Method::Md5 => 8,
+            Method::Sha256 | Method::Sha512 => 16,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct CryptArgs {
+    /// Hash method
+    #[arg(short, long, value_enum, default_value_t = Method::Sha512)]
+    method: Method,
+
+    /// Check the password against this hash (or a whole /etc/shadow line)
+    /// instead of creating one
+    #[arg(long, value_name = "HASH", conflicts_with_all = ["method", "salt", "rounds"])]
+    verify: Option<String>,
+
+    /// Salt (at most 8 characters for md5, 16 for sha256 and sha512;
+    /// random by default)
+    #[arg(long)]
+    salt: Option<String>,
+
+    /// Rounds for sha256 and sha512 (1000 to 999999999, default 5000)
+    #[arg(long, value_name = "N")]
+    rounds: Option<u32>,
+
+    /// Read the password from stdin instead of prompting for it
+    #[arg(long)]
+    stdin: bool,
+}
+
+/// Encode `digest` in crypt's base64, taking bytes in the given order.
+fn encode(digest: &[u8], order: &[[usize; 3]], out: &mut String) {
+    let last = order.len() - 1;
+    for (i, &[a, b, c]) in order.iter().enumerate() {
+        // The final group holds the one or two bytes left over
+        let (value, chars) = match (i == last, digest.len() % 3) {
+            (true, 1) => (digest[a] as u32, 2),
+            (true, 2) => ((digest[a] as u32) << 8 | digest[b] as u32, 3),
+            _ => (
+                (digest[a] as u32) << 16 | (digest[b] as u32) << 8 | digest[c] as u32,
+                4,
+            ),
+        };
+        for shift in 0..chars {
+            out.push(ALPHABET[(value >> (6 * shift)) as usize & 0x3f] as char);
+        }
+    }
+}
+
+fn md5_crypt(password: &[u8], salt: &[u8]) -> String {
+    let alternate = Md5::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+
+    let mut context = Md5::new()
+        .chain_update(password)
+        .chain_update(b"$1$")
+        .chain_update(salt);
+    for chunk in password.chunks(16) {
+        context.update(&alternate[..chunk.len()]);
+    }
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            context.update([0]);
+        } else {
+            context.update(&password[..1]);
+        }
+        length >>= 1;
+    }
+    let mut digest = context.finalize();
+
+    for round in 0..1000 {
+        let mut context = Md5::new();
+        if round & 1 == 1 {
+            context.update(password);
+        } else {
+            context.update(digest);
+        }
+        if round % 3 != 0 {
+            context.update(salt);
+        }
+        if round % 7 != 0 {
+            context.update(password);
+        }
+        if round & 1 == 1 {
+            context.update(digest);
+        } else {
+            context.update(password);
+        }
+        digest = context.finalize();
+    }
+
+    let mut out = format!("$1${}$", String::from_utf8_lossy(salt));
+    encode(&digest, &MD5_ORDER, &mut out);
+    out
+}
+
+/// `source` repeated to fill `len` bytes
+fn repeat_to(source: &[u8], len: usize) -> Vec<u8> {
+    source.iter().copied().cycle().take(len).collect()
+}
+
+fn sha_crypt<D: Digest>(
+    password: &[u8],
+    salt: &[u8],
+    rounds: Option<u32>,
+    prefix: &str,
+    order: &[[usize; 3]],
+) -> String {
+    let hash_len = <D as Digest>::output_size();
+
+    let b = D::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+
+    let mut context = D::new().chain_update(password).chain_update(salt);
+    let mut remaining = password.len();
+    while remaining > hash_len {
+        context.update(&b);
+        remaining -= hash_len;
+    }
+    context.update(&b[..remaining]);
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            context.update(&b);
+        } else {
+            context.update(password);
+        }
+        length >>= 1;
+    }
+    let a = context.finalize();
+
+    let mut context = D::new();
+    for _ in 0..password.len() {
+        context.update(password);
+    }
+    let p = repeat_to(&context.finalize(), password.len());
+
+    let mut context = D::new();
+    for _ in 0..16 + a[0] as usize {
+        context.update(salt);
+    }
+    let s = repeat_to(&context.finalize(), salt.len());
+
+    let mut c = a;
+    for round in 0..rounds.unwrap_or(DEFAULT_ROUNDS) {
+        let mut context = D::new();
+        if round & 1 == 1 {
+            context.update(&p);
+        } else {
+            context.update(&c);
+        }
+        if round % 3 != 0 {
+            context.update(&s);
+        }
+        if round % 7 != 0 {
+            context.update(&p);
+        }
+        if round & 1 == 1 {
+            context.update(&c);
+        } else {
+            context.update(&p);
+        }
+        c = context.finalize();
+    }
+
+    let mut out = prefix.to_string();
+    if let Some(rounds) = rounds {
+        out.push_str(&format!("rounds={}$", rounds));
+    }
+    out.push_str(&String::from_utf8_lossy(salt));
+    out.push('$');
+    encode(&c, order, &mut out);
+    out
+}
+
+/// Hash `password` with `method`. Salts longer than the method allows are
+/// truncated and rounds are clamped to the allowed range, as glibc does.
+fn crypt(method: Method, password: &[u8], salt: &str, rounds: Option<u32>) -> String {
+    let salt = &salt.as_bytes()[..salt.len().min(method.max_salt_len())];
+    let rounds = rounds.map(|r| r.clamp(MIN_ROUNDS, MAX_ROUNDS));
+    match method {
+        Method::Md5 => md5_crypt(password, salt),
+        Method::Sha256 => sha_crypt::<Sha256>(password, salt, rounds, "$5$", &SHA256_ORDER),
+        Method::Sha512 => sha_crypt::<Sha512>(password, salt, rounds, "$6$", &SHA512_ORDER),
+    }
+}
+
+fn random_salt(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+        .iter()
+        .map(|&b| ALPHABET[(b & 0x3f) as usize] as char)
+        .collect()
+}
+
+/// Check `password` against a crypt hash, or the hash field of a shadow
+/// line.
+fn verify(field: &str, password: &[u8]) -> Result<bool, String> {
+    let hash = match field.split(':').nth(1) {
+        Some(hash) => hash,
+        None => field,
+    };
+    let method = [Method::Md5, Method::Sha256, Method::Sha512]
+        .into_iter()
+        .find(|m| hash.starts_with(m.prefix()))
+        .ok_or_else(|| "not a $1$, $5$ or $6$ hash".to_string())?;
+
+    let mut rest = &hash[method.prefix().len()..];
+    let mut rounds = None;
+    if method != Method::Md5 {
+        if let Some(params) = rest.strip_prefix("rounds=") {
+            let (value, after) = params
+                .split_once('$')
+                .ok_or_else(|| "malformed hash".to_string())?;
+            rounds = Some(value.parse().map_err(|_| "invalid rounds".to_string())?);
+            rest = after;
+        }
+    }
+    let (salt, _) = rest
+        .split_once('$')
+        .ok_or_else(|| "malformed hash".to_string())?;
+
+    Ok(crypt(method, password, salt, rounds) == hash)
+}
+
+/// Run the `crypt` subcommand. Returns `Ok(false)` if verification failed.
+pub fn run(args: &CryptArgs) -> Result<bool, String> {
+    if let Some(salt) = &args.salt {
+        if !salt.is_ascii() || salt.contains(['$', ':', '\n']) {
+            return Err("salts must be ASCII without '$', ':' or newlines".to_string());
+        }
+    }
+    if args.rounds.is_some() && args.method == Method::Md5 {
+        return Err("--rounds does not apply to md5".to_string());
+    }
+    let password =
+        kdf::read_password(args.stdin).map_err(|e| format!("cannot read password: {}", e))?;
+
+    match &args.verify {
+        Some(field) => {
+            let ok = verify(field, &password)?;
+            if ok {
+                println!("{}", "OK".green().bold());
+            } else {
+                println!("{}", "FAILED".red().bold());
+            }
+            Ok(ok)
+        }
+        None => {
+            let salt = match &args.salt {
+                Some(salt) => salt.clone(),
+                None => random_salt(args.method.max_salt_len()),
+            };
+            println!("{}", crypt(args.method, &password, &salt, args.rounds));
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The test vectors from Drepper's specification
+    const SHA_VECTORS: [(Method, &str, Option<u32>, &str, &str); 14] = [
+        (Method::Sha256, "saltstring", None, "Hello world!",
+         "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"),
+        (Method::Sha256, "saltstringsaltstring", Some(10000), "Hello world!",
+         "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLeujZkZRBAwqFMz2.opqey6IcA"),
+        (Method::Sha256, "toolongsaltstring", Some(5000), "This is just a test",
+         "$5$rounds=5000$toolongsaltstrin$Un/5jzAHMgOGZ5.mWJpuVolil07guHPvOW8mGRcvxa5"),
+        (Method::Sha256, "anotherlongsaltstring", Some(1400),
+         "a very much longer text to encrypt.  This one even stretches over morethan one line.",
+         "$5$rounds=1400$anotherlongsalts$Rx.j8H.h8HjEDGomFU8bDkXm3XIUnzyxf12oP84Bnq1"),
+        (Method::Sha256, "short", Some(77777),
+         "we have a short salt string but not a short password",
+         "$5$rounds=77777$short$JiO1O3ZpDAxGJeaDIuqCoEFysAe1mZNJRs3pw0KQRd/"),
+        (Method::Sha256, "asaltof16chars..", Some(123456), "a short string",
+         "$5$rounds=123456$asaltof16chars..$gP3VQ/6X7UUEW3HkBn2w1/Ptq2jxPyzV/cZKmF/wJvD"),
+        (Method::Sha256, "roundstoolow", Some(10), "the minimum number is still observed",
+         "$5$rounds=1000$roundstoolow$yfvwcWrQ8l/K0DAWyuPMDNHpIVlTQebY9l/gL972bIC"),
+        (Method::Sha512, "saltstring", None, "Hello world!",
+         "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"),
+        (Method::Sha512, "saltstringsaltstring", Some(10000), "Hello world!",
+         "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v."),
+        (Method::Sha512, "toolongsaltstring", Some(5000), "This is just a test",
+         "$6$rounds=5000$toolongsaltstrin$lQ8jolhgVRVhY4b5pZKaysCLi0QBxGoNeKQzQ3glMhwllF7oGDZxUhx1yxdYcz/e1JSbq3y6JMxxl8audkUEm0"),
+        (Method::Sha512, "anotherlongsaltstring", Some(1400),
+         "a very much longer text to encrypt.  This one even stretches over morethan one line.",
+         "$6$rounds=1400$anotherlongsalts$POfYwTEok97VWcjxIiSOjiykti.o/pQs.wPvMxQ6Fm7I6IoYN3CmLs66x9t0oSwbtEW7o7UmJEiDwGqd8p4ur1"),
+        (Method::Sha512, "short", Some(77777),
+         "we have a short salt string but not a short password",
+         "$6$rounds=77777$short$WuQyW2YR.hBNpjjRhpYD/ifIw05xdfeEyQoMxIXbkvr0gge1a1x3yRULJ5CCaUeOxFmtlcGZelFl5CxtgfiAc0"),
+        (Method::Sha512, "asaltof16chars..", Some(123456), "a short string",
+         "$6$rounds=123456$asaltof16chars..$BtCwjqMJGx5hrJhZywWvt0RLE8uZ4oPwcelCjmw2kSYu.Ec6ycULevoBK25fs2xXgMNrCzIMVcgEJAstJeonj1"),
+        (Method::Sha512, "roundstoolow", Some(10), "the minimum number is still observed",
+         "$6$rounds=1000$roundstoolow$kUMsbe306n21p9R.FRkW3IGn.S9NPN0x50YhH1xhLsPuWGsUSklZt58jaTfF4ZEQpyUNGc0dqbpBYYBaHHrsX."),
+    ];
+
+    #[test]
+    fn sha_crypt_vectors() {
+        for (method, salt, rounds, password, expected) in SHA_VECTORS {
+            assert_eq!(crypt(method, password.as_bytes(), salt, rounds), expected);
+        }
+    }
+
+    #[test]
+    fn md5_crypt_vectors() {
+        let vectors = [
+            (
+                "saltstri",
+                "Hello world!",
+                "$1$saltstri$YMyguxXMBpd2TEZ.vS/3q1",
+            ),
+            ("", "password", "$1$$I2o9Z7NcvQAKp7wyCTlia0"),
+            ("12345678", "password", "$1$12345678$o2n/JiO/h5VviOInWJ4OQ/"),
+            // Salts are cut to 8 characters
+            (
+                "saltstring",
+                "This is just a test",
+                "$1$saltstri$ufSt4fO66XiAxKBT488aF1",
+            ),
+        ];
+        for (salt, password, expected) in vectors {
+            assert_eq!(
+                crypt(Method::Md5, password.as_bytes(), salt, None),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn verify_round_trip() {
+        for method in [Method::Md5, Method::Sha256, Method::Sha512] {
+            let hash = crypt(method, b"hunter2", "0123456789abcdef", Some(2000));
+            let shadow = format!("alice:{}:19700:0:99999:7:::", hash);
+            assert_eq!(verify(&hash, b"hunter2"), Ok(true));
+            assert_eq!(verify(&shadow, b"hunter2"), Ok(true));
+            assert_eq!(verify(&shadow, b"hunter3"), Ok(false));
+        }
+        assert!(verify("$2b$12$abc", b"hunter2").is_err());
+    }
+}
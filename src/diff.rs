@@ -0,0 +1,218 @@
+//! Comparing two manifests for the `diff` subcommand.
+//!
+//! Paths are compared on the digests both manifests have for them. A path
+//! only in the new manifest whose digests match a path only in the old one
+//! is reported as renamed rather than as a removal and an addition.
+
+use crate::encoding::Encoding;
+use crate::hasher::{Algorithm, Options};
+use crate::manifest::{self, Entry};
+use colored::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    /// The older manifest, in any format shall writes
+    old: PathBuf,
+
+    /// The newer manifest
+    new: PathBuf,
+
+    /// How digests are written in both manifests
+    #[arg(long, value_enum, default_value_t = Encoding::Hex)]
+    encoding: Encoding,
+}
+
+/// Digests of one path, keyed by algorithm label
+type Digests = BTreeMap<String, Vec<u8>>;
+
+/// Digests of every path in a manifest
+type Manifest = BTreeMap<String, Digests>;
+
+/// Read a manifest into a map from path to its digests. GNU-style lines
+/// don't name their algorithm, so it's guessed from the digest length.
+fn load(path: &Path, encoding: Encoding) -> Result<Manifest, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let entries = manifest::parse_any(&contents, encoding)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if entries.is_empty() {
+        return Err(format!("{}: no digests found", path.display()));
+    }
+
+    let mut files = Manifest::new();
+    for Entry {
+        algorithm,
+        hmac,
+        digest,
+        path: name,
+    } in entries
+    {
+        let algorithm = algorithm.or_else(|| {
+            Algorithm::ALL
+                .into_iter()
+                .find(|a| a.output_len(&Options::default()) == digest.len())
+        });
+        let label = match algorithm {
+            Some(algorithm) => algorithm.label(hmac),
+            None => format!("{}-byte digest", digest.len()),
+        };
+        files.entry(name).or_default().insert(label, digest);
+    }
+    Ok(files)
+}
+
+/// Whether `old` and `new` have the same contents, judged by the digests
+/// they share. `None` if they have no algorithm in common.
+fn same(old: &Digests, new: &Digests) -> Option<bool> {
+    let mut common = old
+        .iter()
+        .filter_map(|(label, digest)| Some(new.get(label)? == digest))
+        .peekable();
+    common.peek()?;
+    Some(common.all(|equal| equal))
+}
+
+/// Pair each of the `added` paths with the first of the `removed` paths
+/// whose digests match on the algorithms both have, as [`same`] compares
+/// them. Both lists are sorted, so the pairing is deterministic.
+fn renames<'a>(
+    old: &'a Manifest,
+    new: &'a Manifest,
+    removed: &[&'a String],
+    added: &[&'a String],
+) -> Vec<(&'a String, &'a String)> {
+    let mut by_digest: HashMap<(&String, &Vec<u8>), Vec<&String>> = HashMap::new();
+    for &path in removed {
+        for digest in &old[path] {
+            by_digest.entry(digest).or_default().push(path);
+        }
+    }
+
+    let mut renamed = Vec::new();
+    let mut taken = HashSet::new();
+    for &path in added {
+        let from = new[path]
+            .iter()
+            .filter_map(|digest| by_digest.get(&digest))
+            .flatten()
+            .filter(|from| !taken.contains(*from))
+            .filter(|from| same(&old[**from], &new[path]) == Some(true))
+            .min();
+        if let Some(&from) = from {
+            taken.insert(from);
+            renamed.push((from, path));
+        }
+    }
+    renamed
+}
+
+/// Run the `diff` subcommand. Returns `Ok(false)` if anything changed.
+pub fn run(args: &DiffArgs) -> Result<bool, String> {
+    let old = load(&args.old, args.encoding)?;
+    let new = load(&args.new, args.encoding)?;
+
+    let mut modified = Vec::new();
+    for (path, old_digests) in &old {
+        if let Some(new_digests) = new.get(path) {
+            match same(old_digests, new_digests) {
+                Some(true) => {}
+                Some(false) => modified.push(path),
+                None => return Err(format!("no common algorithm for {}", path)),
+            }
+        }
+    }
+
+    let mut removed: Vec<&String> = old.keys().filter(|p| !new.contains_key(*p)).collect();
+    let mut added: Vec<&String> = new.keys().filter(|p| !old.contains_key(*p)).collect();
+
+    let renamed = renames(&old, &new, &removed, &added);
+    let from: HashSet<&String> = renamed.iter().map(|&(from, _)| from).collect();
+    let to: HashSet<&String> = renamed.iter().map(|&(_, to)| to).collect();
+    removed.retain(|path| !from.contains(path));
+    added.retain(|path| !to.contains(path));
+
+    for path in &added {
+        println!("{} {}", "+".green().bold(), path);
+    }
+    for path in &removed {
+        println!("{} {}", "-".red().bold(), path);
+    }
+    for path in &modified {
+        println!("{} {}", "~".yellow().bold(), path);
+    }
+    for (from, to) in &renamed {
+        println!("{} {} -> {}", ">".cyan().bold(), from, to);
+    }
+
+    let changes = added.len() + removed.len() + modified.len() + renamed.len();
+    if changes == 0 {
+        println!("{}", "No changes".green().bold());
+    } else {
+        println!(
+            "{} added, {} removed, {} modified, {} renamed",
+            added.len(),
+            removed.len(),
+            modified.len(),
+            renamed.len()
+        );
+    }
+    Ok(changes == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path and its `(label, digest)` pairs
+    type File<'a> = (&'a str, &'a [(&'a str, &'a [u8])]);
+
+    fn manifest(files: &[File]) -> Manifest {
+        files
+            .iter()
+            .map(|(path, digests)| {
+                let digests = digests
+                    .iter()
+                    .map(|(label, digest)| (label.to_string(), digest.to_vec()))
+                    .collect();
+                (path.to_string(), digests)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renames_match_on_shared_algorithms() {
+        let old = manifest(&[("a", &[("SHA256", b"1")]), ("old", &[("SHA256", b"2")])]);
+        let new = manifest(&[
+            ("b", &[("SHA256", b"1"), ("SHA512", b"x")]),
+            ("new", &[("SHA256", b"2"), ("SHA512", b"y")]),
+        ]);
+        let removed: Vec<&String> = old.keys().collect();
+        let added: Vec<&String> = new.keys().collect();
+        let renamed: Vec<(&str, &str)> = renames(&old, &new, &removed, &added)
+            .into_iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect();
+        assert_eq!(renamed, [("a", "b"), ("old", "new")]);
+    }
+
+    #[test]
+    fn renames_need_every_shared_digest_to_match() {
+        let old = manifest(&[("a", &[("SHA256", b"1"), ("MD5", b"m")])]);
+        let new = manifest(&[("b", &[("SHA256", b"1"), ("MD5", b"n")])]);
+        let removed: Vec<&String> = old.keys().collect();
+        let added: Vec<&String> = new.keys().collect();
+        assert!(renames(&old, &new, &removed, &added).is_empty());
+    }
+
+    #[test]
+    fn each_removed_path_is_renamed_once() {
+        let old = manifest(&[("a", &[("SHA256", b"1")])]);
+        let new = manifest(&[("b", &[("SHA256", b"1")]), ("c", &[("SHA256", b"1")])]);
+        let removed: Vec<&String> = old.keys().collect();
+        let added: Vec<&String> = new.keys().collect();
+        assert_eq!(renames(&old, &new, &removed, &added).len(), 1);
+    }
+}
@@ -0,0 +1,537 @@
+//! Finding files with identical contents for the `dupes` subcommand.
+//!
+//! Only files that share a size can be duplicates, so files are bucketed by
+//! size first. Within a bucket, the first [`HEAD_LEN`] bytes are hashed to
+//! split it further, and only files that still collide get a full hash.
+//! Paths that are already hard links to the same file count as one file.
+//! Symlinks are skipped, so `--link` never follows or replaces one.
+//! Before a file is replaced, it and the file kept in its place are
+//! checked against their size, modification time and inode at hashing
+//! time, and left alone if either changed in the meantime.
+
+use crate::color;
+use crate::hasher::{self, Algorithm, Digests, Options};
+use crate::walk::{self, SortOrder, WalkOptions};
+use clap::ValueEnum;
+use colored::*;
+use password_hash::rand_core::{OsRng, RngCore};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How many bytes of each file the first pass hashes
+const HEAD_LEN: u64 = 4096;
+
+/// Algorithm used for both passes
+const ALGORITHM: Algorithm = Algorithm::Blake3;
+
+/// How many temporary names `replace` tries before giving up
+const TEMPORARY_ATTEMPTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LinkMode {
+    /// Hard links; every copy then shares one set of metadata
+    Hardlink,
+    /// Copy-on-write clones, on file systems that support them (Btrfs, XFS,
+    /// APFS, ReFS)
+    Reflink,
+}
+
+#[derive(clap::Args)]
+pub struct DupesArgs {
+    /// Directories to search
+    #[arg(required = true, value_name = "DIR")]
+    dirs: Vec<PathBuf>,
+
+    /// Include subdirectories
+    #[arg(long)]
+    recursive: bool,
+
+    /// Only look at files matching this glob (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files matching this glob (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Skip files listed in .gitignore and .ignore files
+    #[arg(long)]
+    gitignore: bool,
+
+    /// Descend into symlinked directories
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Skip files and directories whose name starts with a dot
+    #[arg(long)]
+    skip_hidden: bool,
+
+    /// Ignore files smaller than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = 1)]
+    min_size: u64,
+
+    /// Number of files to hash at once (default: number of CPUs)
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Only print how many duplicates there are and how much space they
+    /// take up, instead of listing them
+    #[arg(long)]
+    summary: bool,
+
+    /// Replace every duplicate with a link to the first file of its group
+    #[arg(long, value_enum, value_name = "MODE")]
+    link: Option<LinkMode>,
+
+    /// Print what --link would do without changing anything
+    #[arg(long, requires = "link")]
+    dry_run: bool,
+}
+
+/// A file, under every path found for it
+struct Candidate {
+    size: u64,
+    modified: Option<SystemTime>,
+    id: Option<(u64, u64)>,
+    paths: Vec<PathBuf>,
+}
+
+/// Identifies a file regardless of which hard link it was found through
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Check that `path` is still the file `candidate` was made from, so a
+/// file written to after it was hashed is never replaced or linked to.
+fn unchanged(path: &Path, candidate: &Candidate) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink()
+        || metadata.len() != candidate.size
+        || metadata.modified().ok() != candidate.modified
+        || file_id(&metadata) != candidate.id
+    {
+        return Err(io::Error::other(format!(
+            "{} changed since it was hashed",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+fn report(path: &Path, e: &io::Error) {
+    let context = format!("Error reading {}", path.display());
+    eprintln!("{}: {}", color::stderr(context.red().bold()), e);
+}
+
+/// Walk every directory in `args`, returning the files found in order.
+/// Files found more than once, through overlapping directories or hard
+/// links, become one candidate.
+fn collect(args: &DupesArgs, ok: &mut bool) -> Vec<Candidate> {
+    let options = WalkOptions {
+        recursive: args.recursive,
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        gitignore: args.gitignore,
+        follow_symlinks: args.follow_symlinks,
+        skip_hidden: args.skip_hidden,
+        symlinks: false,
+    };
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut seen: HashMap<(u64, u64), usize> = HashMap::new();
+    for dir in &args.dirs {
+        let walker = match walk::walk(dir, &options) {
+            Ok(walker) => walker,
+            Err(e) => {
+                report(dir, &e);
+                *ok = false;
+                continue;
+            }
+        };
+        let mut files = Vec::new();
+        for entry in walker {
+            match entry {
+                Ok(entry) => files.push(entry),
+                Err(e) => {
+                    report(dir, &e);
+                    *ok = false;
+                }
+            }
+        }
+        walk::sort(&mut files, SortOrder::Name);
+
+        for file in files {
+            let metadata = match fs::symlink_metadata(&file.path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => continue,
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    report(&file.path, &e);
+                    *ok = false;
+                    continue;
+                }
+            };
+            if metadata.len() < args.min_size {
+                continue;
+            }
+            let id = file_id(&metadata);
+            match id {
+                Some(id) if seen.contains_key(&id) => {
+                    let paths = &mut candidates[seen[&id]].paths;
+                    if !paths.contains(&file.path) {
+                        paths.push(file.path);
+                    }
+                }
+                _ => {
+                    if let Some(id) = id {
+                        seen.insert(id, candidates.len());
+                    }
+                    candidates.push(Candidate {
+                        size: metadata.len(),
+                        modified: metadata.modified().ok(),
+                        id,
+                        paths: vec![file.path],
+                    });
+                }
+            }
+        }
+    }
+    candidates
+}
+
+/// Split each group of candidate indices by the digest of their first
+/// `limit` bytes, keeping the groups that still have more than one file.
+fn refine(
+    groups: Vec<Vec<usize>>,
+    candidates: &[Candidate],
+    limit: u64,
+    jobs: usize,
+    ok: &mut bool,
+) -> io::Result<Vec<Vec<usize>>> {
+    let indices: Vec<usize> = groups.iter().flatten().copied().collect();
+    let paths: Vec<&Path> = indices
+        .iter()
+        .map(|&i| candidates[i].paths[0].as_path())
+        .collect();
+
+    // Keep digests from different groups apart, since groups differ in size
+    let group_of: Vec<usize> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, members)| std::iter::repeat_n(group, members.len()))
+        .collect();
+
+    let mut by_digest: HashMap<(usize, Digests), Vec<usize>> = HashMap::new();
+    hasher::hash_file_heads(
+        &paths,
+        limit,
+        &[ALGORITHM],
+        &Options::default(),
+        jobs.min(paths.len().max(1)),
+        |n, result| {
+            match result {
+                Ok((_, digests)) => by_digest
+                    .entry((group_of[n], digests))
+                    .or_default()
+                    .push(indices[n]),
+                Err(e) => {
+                    report(paths[n], &e);
+                    *ok = false;
+                }
+            }
+            Ok(())
+        },
+    )?;
+
+    let mut refined: Vec<Vec<usize>> = by_digest
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    refined.sort();
+    Ok(refined)
+}
+
+/// Replace `path` with a link to `keep`. The link is made next to `path`
+/// and renamed over it, so `path` is never missing.
+fn replace(keep: &Path, path: &Path, mode: LinkMode) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut suffix = String::new();
+    let mut temporary = None;
+    for _ in 0..TEMPORARY_ATTEMPTS {
+        let candidate = path.with_file_name(format!(".{}{}.shall-dupes", name, suffix));
+        // Both fail with AlreadyExists rather than touch an existing file
+        let created = match mode {
+            LinkMode::Hardlink => fs::hard_link(keep, &candidate),
+            LinkMode::Reflink => reflink_copy::reflink(keep, &candidate),
+        };
+        match created {
+            Ok(()) => {
+                temporary = Some(candidate);
+                break;
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                suffix = format!(".{:08x}", OsRng.next_u32());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let temporary = temporary.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "no free name for a temporary file",
+        )
+    })?;
+
+    // The temporary is ours now, so it's safe to remove on failure
+    let result = match mode {
+        LinkMode::Hardlink => Ok(()),
+        LinkMode::Reflink => fs::metadata(path)
+            .and_then(|metadata| fs::set_permissions(&temporary, metadata.permissions())),
+    }
+    .and_then(|()| fs::rename(&temporary, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Run the `dupes` subcommand. Returns `Ok(false)` if a file could not be
+/// read or replaced.
+pub fn run(args: &DupesArgs) -> Result<bool, String> {
+    let mut ok = true;
+    let candidates = collect(args, &mut ok);
+    let jobs = args.jobs.map_or_else(
+        || std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        NonZeroUsize::get,
+    );
+
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        by_size.entry(candidate.size).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = by_size
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    groups.sort();
+
+    let hash_error = |e: io::Error| format!("cannot hash files: {}", e);
+    groups = refine(groups, &candidates, HEAD_LEN, jobs, &mut ok).map_err(hash_error)?;
+    // Files no longer than HEAD_LEN have been hashed in full already
+    let (small, large): (Vec<_>, Vec<_>) = groups
+        .into_iter()
+        .partition(|group| candidates[group[0]].size <= HEAD_LEN);
+    groups = refine(large, &candidates, u64::MAX, jobs, &mut ok).map_err(hash_error)?;
+    groups.extend(small);
+    groups.sort();
+
+    let duplicates: usize = groups.iter().map(|group| group.len() - 1).sum();
+    let reclaimable: u64 = groups
+        .iter()
+        .map(|group| candidates[group[0]].size * (group.len() as u64 - 1))
+        .sum();
+
+    if let Some(mode) = args.link {
+        let verb = match (mode, args.dry_run) {
+            (LinkMode::Hardlink, false) => "Linked",
+            (LinkMode::Reflink, false) => "Reflinked",
+            (LinkMode::Hardlink, true) => "Would link",
+            (LinkMode::Reflink, true) => "Would reflink",
+        };
+        let mut reclaimed = 0;
+        for group in &groups {
+            let keep = &candidates[group[0]].paths[0];
+            for &i in &group[1..] {
+                let mut replaced = true;
+                for path in &candidates[i].paths {
+                    let result = if args.dry_run {
+                        Ok(())
+                    } else {
+                        unchanged(keep, &candidates[group[0]])
+                            .and_then(|()| unchanged(path, &candidates[i]))
+                            .and_then(|()| replace(keep, path, mode))
+                    };
+                    match result {
+                        Ok(()) => println!(
+                            "{} {} -> {}",
+                            verb.green().bold(),
+                            path.display(),
+                            keep.display()
+                        ),
+                        Err(e) => {
+                            let context = format!("Error replacing {}", path.display());
+                            eprintln!("{}: {}", color::stderr(context.red().bold()), e);
+                            replaced = false;
+                            ok = false;
+                        }
+                    }
+                }
+                // Space is only freed once no path refers to the old file
+                if replaced {
+                    reclaimed += candidates[i].size;
+                }
+            }
+        }
+        let verb = if args.dry_run {
+            "Would reclaim"
+        } else {
+            "Reclaimed"
+        };
+        println!("{} {} bytes", verb, reclaimed);
+    } else if args.summary {
+        println!(
+            "{} duplicate file(s) in {} group(s), {} bytes reclaimable",
+            duplicates,
+            groups.len(),
+            reclaimable
+        );
+    } else {
+        for (n, group) in groups.iter().enumerate() {
+            if n > 0 {
+                println!();
+            }
+            let size = candidates[group[0]].size;
+            println!(
+                "{}",
+                format!("{} files, {} bytes each", group.len(), size).bold()
+            );
+            for &i in group {
+                for path in &candidates[i].paths {
+                    println!("{}", path.display());
+                }
+            }
+        }
+    }
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: DupesArgs,
+    }
+
+    fn run_with(args: &[&str]) -> bool {
+        let cli = Cli::parse_from(std::iter::once("shall").chain(args.iter().copied()));
+        run(&cli.args).unwrap()
+    }
+
+    /// An empty directory under the system's temporary directory
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shall-dupes-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    fn inode(path: &Path) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+        fs::symlink_metadata(path).unwrap().ino()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_never_linked() {
+        let root = scratch("symlinks");
+        let dir = root.join("d");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::create_dir(root.join("other")).unwrap();
+        fs::write(root.join("other/file"), "contents").unwrap();
+        fs::write(dir.join("sub/b"), "contents").unwrap();
+        fs::write(dir.join("sub/c"), "contents").unwrap();
+        std::os::unix::fs::symlink("../other/file", dir.join("a")).unwrap();
+
+        assert!(run_with(&[
+            "--recursive",
+            "--link",
+            "hardlink",
+            dir.to_str().unwrap()
+        ]));
+        assert!(fs::symlink_metadata(dir.join("a")).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(dir.join("sub/b")).unwrap(), "contents");
+        assert_eq!(inode(&dir.join("sub/b")), inode(&dir.join("sub/c")));
+        assert_ne!(inode(&dir.join("sub/b")), inode(&root.join("other/file")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_files_are_not_used_as_temporaries() {
+        let dir = scratch("temporaries");
+        fs::write(dir.join("a"), "contents").unwrap();
+        fs::write(dir.join("b"), "contents").unwrap();
+        fs::write(dir.join(".b.shall-dupes"), "precious").unwrap();
+
+        assert!(run_with(&["--link", "hardlink", dir.to_str().unwrap()]));
+        assert_eq!(
+            fs::read_to_string(dir.join(".b.shall-dupes")).unwrap(),
+            "precious"
+        );
+        assert_eq!(inode(&dir.join("a")), inode(&dir.join("b")));
+        // Only the user's file is left besides the two linked ones
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_changed_after_hashing_are_noticed() {
+        let dir = scratch("changed");
+        for name in ["a", "b", "c", "d"] {
+            fs::write(dir.join(name), "contents").unwrap();
+        }
+        let cli = Cli::parse_from(["shall", dir.to_str().unwrap()]);
+        let mut ok = true;
+        let candidates = collect(&cli.args, &mut ok);
+        assert!(ok);
+        let [a, b, c, d] = &candidates[..] else {
+            panic!("expected four candidates");
+        };
+
+        // Same size, but written to since
+        let modified = fs::metadata(&b.paths[0]).unwrap().modified().unwrap();
+        fs::write(&b.paths[0], "CONTENTS").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&b.paths[0])
+            .unwrap()
+            .set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+        // A different size
+        fs::write(&c.paths[0], "contents, longer").unwrap();
+        // Replaced by another file with the same size and time
+        let other = dir.join("other");
+        fs::write(&other, "CONTENTS").unwrap();
+        let modified = fs::metadata(&d.paths[0]).unwrap().modified().unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&other)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::rename(&other, &d.paths[0]).unwrap();
+
+        assert!(unchanged(&a.paths[0], a).is_ok());
+        assert!(unchanged(&b.paths[0], b).is_err());
+        assert!(unchanged(&c.paths[0], c).is_err());
+        if cfg!(unix) {
+            assert!(unchanged(&d.paths[0], d).is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Text encodings for digests (`--encoding`).
+
+use clap::ValueEnum;
+use data_encoding::{BASE32, BASE64, BASE64URL_NOPAD};
+
+/// The alphabet Nix uses for its base32 store paths and hashes
+const NIX_ALPHABET: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// Lowercase hexadecimal
+    #[default]
+    Hex,
+    /// Uppercase hexadecimal
+    #[value(name = "HEX")]
+    HexUpper,
+    /// Standard base64 with padding, as used by SRI and Content-MD5
+    Base64,
+    /// URL-safe base64 without padding
+    Base64url,
+    /// RFC 4648 base32 with padding
+    Base32,
+    /// Nix's base32, as in `sha256:0abc...`
+    NixBase32,
+    /// Base58 with the Bitcoin alphabet
+    Base58,
+    /// Lowercase hex bytes separated by colons, as in fingerprints
+    ColonHex,
+}
+
+impl Encoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Hex => hex::encode(bytes),
+            Encoding::HexUpper => hex::encode_upper(bytes),
+            Encoding::Base64 => BASE64.encode(bytes),
+            Encoding::Base64url => BASE64URL_NOPAD.encode(bytes),
+            Encoding::Base32 => BASE32.encode(bytes),
+            Encoding::NixBase32 => nix_base32_encode(bytes),
+            Encoding::Base58 => bs58::encode(bytes).into_string(),
+            Encoding::ColonHex => bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+        }
+    }
+
+    /// Decode `text`, being lenient about letter case for the hex
+    /// encodings and about padding for base64url.
+    pub fn decode(self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Hex | Encoding::HexUpper => hex::decode(text).ok(),
+            Encoding::Base64 => BASE64.decode(text.as_bytes()).ok(),
+            Encoding::Base64url => BASE64URL_NOPAD
+                .decode(text.trim_end_matches('=').as_bytes())
+                .ok(),
+            Encoding::Base32 => BASE32.decode(text.as_bytes()).ok(),
+            Encoding::NixBase32 => nix_base32_decode(text),
+            Encoding::Base58 => bs58::decode(text).into_vec().ok(),
+            Encoding::ColonHex => {
+                let parts: Vec<&str> = text.split(':').collect();
+                if parts.iter().any(|p| p.len() != 2) {
+                    return None;
+                }
+                hex::decode(parts.concat()).ok()
+            }
+        }
+    }
+}
+
+/// Nix reads the digest as one little-endian number and prints it five
+/// bits at a time, most significant first.
+fn nix_base32_encode(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let len = (bytes.len() * 8 - 1) / 5 + 1;
+    (0..len)
+        .rev()
+        .map(|n| {
+            let bit = n * 5;
+            let (i, j) = (bit / 8, bit % 8);
+            let low = (bytes[i] >> j) as u16;
+            let high = bytes.get(i + 1).map_or(0, |&b| (b as u16) << (8 - j));
+            NIX_ALPHABET[((low | high) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+fn nix_base32_decode(text: &str) -> Option<Vec<u8>> {
+    let size = text.len() * 5 / 8;
+    let mut bytes = vec![0u8; size];
+    for (n, c) in text.bytes().rev().enumerate() {
+        let digit = NIX_ALPHABET.iter().position(|&a| a == c)? as u16;
+        let bit = n * 5;
+        let (i, j) = (bit / 8, bit % 8);
+        let value = digit << j;
+        if i < size {
+            bytes[i] |= value as u8;
+        } else if value != 0 {
+            return None;
+        }
+        let carry = (value >> 8) as u8;
+        if i + 1 < size {
+            bytes[i + 1] |= carry;
+        } else if carry != 0 {
+            return None;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256, Sha512};
+
+    #[test]
+    fn nix_base32_known_hashes() {
+        let vectors: [(Vec<u8>, &str); 5] = [
+            (
+                Sha256::digest(b"").to_vec(),
+                "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73",
+            ),
+            (
+                Sha256::digest(b"abc").to_vec(),
+                "1b8m03r63zqhnjf7l5wnldhh7c134ap5vpj0850ymkq1iyzicy5s",
+            ),
+            (
+                sha1::Sha1::digest(b"abc").to_vec(),
+                "kpcd173cq987hw957sx6m0868wv3x6d9",
+            ),
+            (md5::Md5::digest(b"abc").to_vec(), "3jgzhjhz9zjvbb0kyj7jc500ch"),
+            (
+                Sha512::digest(b"abc").to_vec(),
+                "2gs8k559z4rlahfx0y688s49m2vvszylcikrfinm30ly9rak69236nkam5ydvly1ai7xac99vxfc4ii84hawjbk876blyk1jfhkbbyx",
+            ),
+        ];
+        for (digest, nix) in vectors {
+            assert_eq!(Encoding::NixBase32.encode(&digest), nix);
+            assert_eq!(Encoding::NixBase32.decode(nix), Some(digest));
+        }
+    }
+
+    #[test]
+    fn nix_base32_rejects_invalid_text() {
+        // 'e', 'o', 'u' and 't' aren't in Nix's alphabet
+        assert_eq!(
+            Encoding::NixBase32.decode("3jgzhjhz9zjvbb0kyj7jc500ce"),
+            None
+        );
+        // The top digit of a 16-byte hash only has three bits to spare
+        assert_eq!(
+            Encoding::NixBase32.decode("zjgzhjhz9zjvbb0kyj7jc500ch"),
+            None
+        );
+    }
+
+    #[test]
+    fn every_encoding_round_trips() {
+        for &encoding in Encoding::value_variants() {
+            for len in 1..=64 {
+                // Include leading zero bytes, which base58 must keep
+                let bytes: Vec<u8> = (0..len).map(|i| (i * 37 % 251) as u8).collect();
+                let text = encoding.encode(&bytes);
+                assert_eq!(
+                    encoding.decode(&text),
+                    Some(bytes),
+                    "{:?} {}",
+                    encoding,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decoding_is_lenient() {
+        assert_eq!(Encoding::Hex.decode("ABCD"), Some(vec![0xab, 0xcd]));
+        assert_eq!(Encoding::HexUpper.decode("abcd"), Some(vec![0xab, 0xcd]));
+        assert_eq!(Encoding::Base64url.decode("-_8="), Some(vec![0xfb, 0xff]));
+        assert_eq!(Encoding::ColonHex.decode("ab:cd"), Some(vec![0xab, 0xcd]));
+        assert_eq!(Encoding::ColonHex.decode("abc:d"), None);
+    }
+}
@@ -0,0 +1,429 @@
+//! Fast non-cryptographic hashes: xxHash, MurmurHash3, FNV-1a and SipHash.
+//!
+//! All of these produce an integer; it is returned as big-endian bytes so
+//! the hex form matches what tools like `xxhsum` print.
+
+use crate::hasher::Hasher;
+use std::hash::Hasher as _;
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh32::Xxh32;
+use xxhash_rust::xxh64::Xxh64;
+
+pub struct Xxh32Hasher(pub Xxh32);
+
+impl Hasher for Xxh32Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.digest().to_be_bytes().to_vec()
+    }
+}
+
+pub struct Xxh64Hasher(pub Xxh64);
+
+impl Hasher for Xxh64Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.digest().to_be_bytes().to_vec()
+    }
+}
+
+/// XXH3, producing either its 64-bit or 128-bit variant
+pub struct Xxh3Hasher {
+    pub state: Xxh3,
+    pub wide: bool,
+}
+
+impl Hasher for Xxh3Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        if self.wide {
+            self.state.digest128().to_be_bytes().to_vec()
+        } else {
+            self.state.digest().to_be_bytes().to_vec()
+        }
+    }
+}
+
+/// Collects input into fixed-size blocks for hashes defined over blocks.
+struct Blocks<const N: usize> {
+    tail: [u8; N],
+    tail_len: usize,
+    total: u64,
+}
+
+impl<const N: usize> Blocks<N> {
+    fn new() -> Self {
+        Blocks {
+            tail: [0; N],
+            tail_len: 0,
+            total: 0,
+        }
+    }
+
+    /// Pass every complete block to `f`, keeping any remainder for later.
+    fn feed(&mut self, mut data: &[u8], mut f: impl FnMut(&[u8; N])) {
+        self.total += data.len() as u64;
+        if self.tail_len > 0 {
+            let take = data.len().min(N - self.tail_len);
+            self.tail[self.tail_len..self.tail_len + take].copy_from_slice(&data[..take]);
+            self.tail_len += take;
+            data = &data[take..];
+            if self.tail_len < N {
+                return;
+            }
+            f(&self.tail);
+            self.tail_len = 0;
+        }
+        let mut blocks = data.chunks_exact(N);
+        for block in &mut blocks {
+            f(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.tail[..rest.len()].copy_from_slice(rest);
+        self.tail_len = rest.len();
+    }
+
+    fn tail(&self) -> &[u8] {
+        &self.tail[..self.tail_len]
+    }
+}
+
+fn fmix32(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^ (k >> 33)
+}
+
+/// MurmurHash3_x86_32
+pub struct Murmur3_32 {
+    h1: u32,
+    blocks: Blocks<4>,
+}
+
+impl Murmur3_32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    pub fn new(seed: u32) -> Self {
+        Murmur3_32 {
+            h1: seed,
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn mix_k1(k1: u32) -> u32 {
+        k1.wrapping_mul(Self::C1)
+            .rotate_left(15)
+            .wrapping_mul(Self::C2)
+    }
+}
+
+impl Hasher for Murmur3_32 {
+    fn update(&mut self, data: &[u8]) {
+        let h1 = &mut self.h1;
+        self.blocks.feed(data, |block| {
+            *h1 ^= Self::mix_k1(u32::from_le_bytes(*block));
+            *h1 = h1.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+        });
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        let mut h1 = self.h1;
+        let tail = self.blocks.tail();
+        if !tail.is_empty() {
+            let mut k1 = 0u32;
+            for (i, &byte) in tail.iter().enumerate() {
+                k1 ^= (byte as u32) << (8 * i);
+            }
+            h1 ^= Self::mix_k1(k1);
+        }
+        h1 ^= self.blocks.total as u32;
+        fmix32(h1).to_be_bytes().to_vec()
+    }
+}
+
+/// MurmurHash3_x64_128, returned as the integer `h2 << 64 | h1`
+pub struct Murmur3_128 {
+    h1: u64,
+    h2: u64,
+    blocks: Blocks<16>,
+}
+
+impl Murmur3_128 {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+    pub fn new(seed: u32) -> Self {
+        Murmur3_128 {
+            h1: seed as u64,
+            h2: seed as u64,
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn mix_k1(k1: u64) -> u64 {
+        k1.wrapping_mul(Self::C1)
+            .rotate_left(31)
+            .wrapping_mul(Self::C2)
+    }
+
+    fn mix_k2(k2: u64) -> u64 {
+        k2.wrapping_mul(Self::C2)
+            .rotate_left(33)
+            .wrapping_mul(Self::C1)
+    }
+}
+
+impl Hasher for Murmur3_128 {
+    fn update(&mut self, data: &[u8]) {
+        let (h1, h2) = (&mut self.h1, &mut self.h2);
+        self.blocks.feed(data, |block| {
+            let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+            let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+
+            *h1 ^= Self::mix_k1(k1);
+            *h1 = h1
+                .rotate_left(27)
+                .wrapping_add(*h2)
+                .wrapping_mul(5)
+                .wrapping_add(0x52dc_e729);
+
+            *h2 ^= Self::mix_k2(k2);
+            *h2 = h2
+                .rotate_left(31)
+                .wrapping_add(*h1)
+                .wrapping_mul(5)
+                .wrapping_add(0x3849_5ab5);
+        });
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        let (mut h1, mut h2) = (self.h1, self.h2);
+        let tail = self.blocks.tail();
+        let (mut k1, mut k2) = (0u64, 0u64);
+        for (i, &byte) in tail.iter().enumerate() {
+            if i < 8 {
+                k1 ^= (byte as u64) << (8 * i);
+            } else {
+                k2 ^= (byte as u64) << (8 * (i - 8));
+            }
+        }
+        if tail.len() > 8 {
+            h2 ^= Self::mix_k2(k2);
+        }
+        if !tail.is_empty() {
+            h1 ^= Self::mix_k1(k1);
+        }
+
+        let len = self.blocks.total;
+        h1 ^= len;
+        h2 ^= len;
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(h1);
+        h1 = fmix64(h1);
+        h2 = fmix64(h2);
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(h1);
+
+        (((h2 as u128) << 64) | h1 as u128).to_be_bytes().to_vec()
+    }
+}
+
+pub struct Fnv1a32(u32);
+
+impl Default for Fnv1a32 {
+    fn default() -> Self {
+        Fnv1a32(0x811c_9dc5)
+    }
+}
+
+impl Hasher for Fnv1a32 {
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = (self.0 ^ byte as u32).wrapping_mul(0x0100_0193);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+pub struct Fnv1a64(u64);
+
+impl Default for Fnv1a64 {
+    fn default() -> Self {
+        Fnv1a64(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a64 {
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+/// SipHash with either 2-4 or 1-3 rounds
+pub enum SipHash {
+    Sip24(siphasher::sip::SipHasher24),
+    Sip13(siphasher::sip::SipHasher13),
+}
+
+impl Hasher for SipHash {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            SipHash::Sip24(hasher) => hasher.write(data),
+            SipHash::Sip13(hasher) => hasher.write(data),
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        let value = match *self {
+            SipHash::Sip24(hasher) => hasher.finish(),
+            SipHash::Sip13(hasher) => hasher.finish(),
+        };
+        value.to_be_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs with their seed, MurmurHash3_x86_32 and MurmurHash3_x64_128
+    const MURMUR3_VECTORS: [(&[u8], u32, &str, &str); 14] = [
+        (b"", 0, "00000000", "00000000000000000000000000000000"),
+        (b"", 1, "514e28b7", "51622daa78f835834610abe56eff5cb5"),
+        (
+            b"",
+            0xffff_ffff,
+            "81f16f39",
+            "857421121ee6446b6af1df4d9d3bc9ec",
+        ),
+        (
+            b"\0\0\0\0",
+            0,
+            "2362f9de",
+            "589623161cf526f1cfa0f7ddd84c76bc",
+        ),
+        (
+            b"a",
+            0x9747_b28c,
+            "7fa09ea6",
+            "9e6dab0f9208f0045ce8d8512db25a1d",
+        ),
+        (
+            b"ab",
+            0x9747_b28c,
+            "74875592",
+            "7eb933e763ce372b8434eead1a44280b",
+        ),
+        (
+            b"abc",
+            0x9747_b28c,
+            "c84a62dd",
+            "cde0a23420b504bf3743630dbfc3cedc",
+        ),
+        (
+            b"abcd",
+            0x9747_b28c,
+            "f0478627",
+            "8a7e67e7e9d3a7bb49b4709eac553791",
+        ),
+        (
+            b"Hello, world!",
+            0x9747_b28c,
+            "24884cba",
+            "f85e7e7631d576baedc485d662a8392e",
+        ),
+        (
+            b"The quick brown fox jumps over the lazy dog",
+            0,
+            "2e4ff723",
+            "7a433ca9c49a9347e34bbc7bbc071b6c",
+        ),
+        (
+            b"The quick brown fox jumps over the lazy dog",
+            0x9747_b28c,
+            "2fa826cd",
+            "f94573727ec016e5738a7f3bd2633121",
+        ),
+        (
+            b"0123456789abcdef",
+            0,
+            "36c7e0df",
+            "87c35b5c63a708da4be06d94cf4ad1a7",
+        ),
+        (
+            b"0123456789abcdefg",
+            42,
+            "834353a6",
+            "4981b28d2f17a7dbd7144105f707cb7c",
+        ),
+        (
+            b"0123456789abcde",
+            42,
+            "3cf4b72f",
+            "c124ca1ee8c6bee784688acce2e6963d",
+        ),
+    ];
+
+    fn digest(mut hasher: Box<dyn Hasher>, chunks: &[&[u8]]) -> String {
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    #[test]
+    fn murmur3_vectors() {
+        for (data, seed, h32, h128) in MURMUR3_VECTORS {
+            assert_eq!(digest(Box::new(Murmur3_32::new(seed)), &[data]), h32);
+            assert_eq!(digest(Box::new(Murmur3_128::new(seed)), &[data]), h128);
+        }
+    }
+
+    #[test]
+    fn murmur3_is_independent_of_chunking() {
+        let data: Vec<u8> = (0..=255).cycle().take(100).collect();
+        let whole_32 = digest(Box::new(Murmur3_32::new(7)), &[&data]);
+        let whole_128 = digest(Box::new(Murmur3_128::new(7)), &[&data]);
+        // Every pair of split points, so tails are filled from empty, part
+        // full and full, and some updates are empty
+        for i in 0..=data.len() {
+            for j in i..=data.len() {
+                let chunks = [&data[..i], &data[i..j], &data[j..]];
+                assert_eq!(digest(Box::new(Murmur3_32::new(7)), &chunks), whole_32);
+                assert_eq!(digest(Box::new(Murmur3_128::new(7)), &chunks), whole_128);
+            }
+        }
+        let bytes: Vec<&[u8]> = data.chunks(1).collect();
+        assert_eq!(digest(Box::new(Murmur3_32::new(7)), &bytes), whole_32);
+        assert_eq!(digest(Box::new(Murmur3_128::new(7)), &bytes), whole_128);
+    }
+}
@@ -0,0 +1,1079 @@
+//! Supported algorithms and the fan-out hasher that feeds every selected
+//! algorithm from a single pass over the input.
+
+use crate::checksum::{self, CrcPreset};
+use crate::fasthash;
+use md4::Md4;
+use md5::{Digest, Md5};
+use ripemd::Ripemd160;
+use sha1::Sha1;
+use sha2::{Sha224, Sha256, Sha384, Sha512, Sha512_224, Sha512_256};
+use sha3::digest::{ExtendableOutput, Update};
+use sha3::{Keccak256, Sha3_224, Sha3_256, Sha3_384, Sha3_512, Shake128, Shake256};
+use siphasher::sip::{SipHasher13, SipHasher24};
+use sm3::Sm3;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use streebog::{Streebog256, Streebog512};
+use tiger::Tiger;
+use whirlpool::Whirlpool;
+use xxhash_rust::xxh3::Xxh3;
+use xxhash_rust::xxh32::Xxh32;
+use xxhash_rust::xxh64::Xxh64;
+
+/// Size of the buffer used when streaming input into the digests
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of chunks a worker may lag behind the reader before it blocks
+const WORKER_BACKLOG: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha512_224,
+    Sha512_256,
+    Sha3_224,
+    Sha3_256,
+    Sha3_384,
+    Sha3_512,
+    Keccak256,
+    Shake128,
+    Shake256,
+    Blake2b,
+    Blake2s,
+    Blake3,
+    /// A CRC from the catalogue
+    Crc(&'static CrcPreset),
+    Adler32,
+    Md5,
+    Ripemd160,
+    Whirlpool,
+    Tiger,
+    Streebog256,
+    Streebog512,
+    Sm3,
+    Md4,
+    Xxh32,
+    Xxh64,
+    Xxh3,
+    Xxh128,
+    Murmur3_32,
+    Murmur3_128,
+    Fnv1a32,
+    Fnv1a64,
+    SipHash24,
+    SipHash13,
+}
+
+/// Secret key material, kept out of `Debug` output
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Key(pub Vec<u8>);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key(<{} bytes redacted>)", self.0.len())
+    }
+}
+
+/// Parameters shared by every algorithm in a run
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Output length in bits for variable-length algorithms
+    pub length: Option<usize>,
+    /// Key for BLAKE2 and SipHash, and for BLAKE3 when `keyed` is set
+    pub key: Option<Key>,
+    /// Use BLAKE3's keyed mode
+    pub keyed: bool,
+    /// Use BLAKE3's key derivation mode with this context string
+    pub derive_key: Option<String>,
+    /// Seed for xxHash and MurmurHash3
+    pub seed: u64,
+    /// Calculate HMAC-<algorithm> with this key instead of the plain digest
+    pub hmac_key: Option<Key>,
+}
+
+impl Algorithm {
+    /// Every algorithm. When guessing an algorithm from a digest length,
+    /// earlier entries win (so SHA256 is preferred over SHA512-256).
+    pub const ALL: [Algorithm; 39] = [
+        Algorithm::Sha1,
+        Algorithm::Sha224,
+        Algorithm::Sha256,
+        Algorithm::Sha384,
+        Algorithm::Sha512,
+        Algorithm::Sha512_224,
+        Algorithm::Sha512_256,
+        Algorithm::Sha3_224,
+        Algorithm::Sha3_256,
+        Algorithm::Sha3_384,
+        Algorithm::Sha3_512,
+        Algorithm::Keccak256,
+        Algorithm::Shake128,
+        Algorithm::Shake256,
+        Algorithm::Blake2b,
+        Algorithm::Blake2s,
+        Algorithm::Blake3,
+        Algorithm::Crc(&checksum::CRC_32),
+        Algorithm::Crc(&checksum::CRC_32C),
+        Algorithm::Crc(&checksum::CRC_64),
+        Algorithm::Adler32,
+        Algorithm::Md5,
+        Algorithm::Ripemd160,
+        Algorithm::Whirlpool,
+        Algorithm::Tiger,
+        Algorithm::Streebog256,
+        Algorithm::Streebog512,
+        Algorithm::Sm3,
+        Algorithm::Md4,
+        Algorithm::Xxh32,
+        Algorithm::Xxh64,
+        Algorithm::Xxh3,
+        Algorithm::Xxh128,
+        Algorithm::Murmur3_32,
+        Algorithm::Murmur3_128,
+        Algorithm::Fnv1a32,
+        Algorithm::Fnv1a64,
+        Algorithm::SipHash24,
+        Algorithm::SipHash13,
+    ];
+
+    /// Algorithms calculated when none are selected
+    pub const DEFAULT: [Algorithm; 4] = [
+        Algorithm::Sha1,
+        Algorithm::Sha256,
+        Algorithm::Sha512,
+        Algorithm::Md5,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha224 => "SHA224",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha384 => "SHA384",
+            Algorithm::Sha512 => "SHA512",
+            Algorithm::Sha512_224 => "SHA512-224",
+            Algorithm::Sha512_256 => "SHA512-256",
+            Algorithm::Sha3_224 => "SHA3-224",
+            Algorithm::Sha3_256 => "SHA3-256",
+            Algorithm::Sha3_384 => "SHA3-384",
+            Algorithm::Sha3_512 => "SHA3-512",
+            Algorithm::Keccak256 => "KECCAK-256",
+            Algorithm::Shake128 => "SHAKE128",
+            Algorithm::Shake256 => "SHAKE256",
+            Algorithm::Blake2b => "BLAKE2b",
+            Algorithm::Blake2s => "BLAKE2s",
+            Algorithm::Blake3 => "BLAKE3",
+            Algorithm::Crc(preset) => preset.name,
+            Algorithm::Adler32 => "ADLER32",
+            Algorithm::Md5 => "MD5",
+            Algorithm::Ripemd160 => "RIPEMD160",
+            Algorithm::Whirlpool => "WHIRLPOOL",
+            Algorithm::Tiger => "TIGER",
+            Algorithm::Streebog256 => "STREEBOG-256",
+            Algorithm::Streebog512 => "STREEBOG-512",
+            Algorithm::Sm3 => "SM3",
+            Algorithm::Md4 => "MD4",
+            Algorithm::Xxh32 => "XXH32",
+            Algorithm::Xxh64 => "XXH64",
+            Algorithm::Xxh3 => "XXH3",
+            Algorithm::Xxh128 => "XXH128",
+            Algorithm::Murmur3_32 => "MURMUR3-32",
+            Algorithm::Murmur3_128 => "MURMUR3-128",
+            Algorithm::Fnv1a32 => "FNV1A-32",
+            Algorithm::Fnv1a64 => "FNV1A-64",
+            Algorithm::SipHash24 => "SIPHASH-2-4",
+            Algorithm::SipHash13 => "SIPHASH-1-3",
+        }
+    }
+
+    /// Look up an algorithm by its (case-insensitive) name
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|a| a.name().eq_ignore_ascii_case(name))
+            .or_else(|| checksum::preset(name).map(Algorithm::Crc))
+    }
+
+    /// Whether the output length can be chosen with `--length`
+    pub fn is_variable_length(self) -> bool {
+        matches!(
+            self,
+            Algorithm::Shake128
+                | Algorithm::Shake256
+                | Algorithm::Blake2b
+                | Algorithm::Blake2s
+                | Algorithm::Blake3
+        )
+    }
+
+    /// Whether the output is an integer, which is also shown in decimal
+    pub fn is_integer(self) -> bool {
+        matches!(
+            self,
+            Algorithm::Crc(_)
+                | Algorithm::Adler32
+                | Algorithm::Xxh32
+                | Algorithm::Xxh64
+                | Algorithm::Xxh3
+                | Algorithm::Xxh128
+                | Algorithm::Murmur3_32
+                | Algorithm::Murmur3_128
+                | Algorithm::Fnv1a32
+                | Algorithm::Fnv1a64
+                | Algorithm::SipHash24
+                | Algorithm::SipHash13
+        )
+    }
+
+    /// Size of the blocks the digest consumes, which HMAC pads its key to.
+    /// `None` for XOFs and checksums, which HMAC is not defined for.
+    pub fn block_size(self) -> Option<usize> {
+        match self {
+            Algorithm::Sha1
+            | Algorithm::Sha224
+            | Algorithm::Sha256
+            | Algorithm::Blake2s
+            | Algorithm::Blake3
+            | Algorithm::Md5
+            | Algorithm::Ripemd160
+            | Algorithm::Whirlpool
+            | Algorithm::Tiger
+            | Algorithm::Streebog256
+            | Algorithm::Streebog512
+            | Algorithm::Sm3
+            | Algorithm::Md4 => Some(64),
+            Algorithm::Sha384
+            | Algorithm::Sha512
+            | Algorithm::Sha512_224
+            | Algorithm::Sha512_256
+            | Algorithm::Blake2b => Some(128),
+            // The sponge rate, as in FIPS 202's HMAC-SHA3 parameters
+            Algorithm::Sha3_224 => Some(144),
+            Algorithm::Sha3_256 | Algorithm::Keccak256 => Some(136),
+            Algorithm::Sha3_384 => Some(104),
+            Algorithm::Sha3_512 => Some(72),
+            _ => None,
+        }
+    }
+
+    /// Name to show for the digest, with an `HMAC-` prefix in HMAC mode
+    pub fn label(self, hmac: bool) -> String {
+        if hmac {
+            format!("HMAC-{}", self.name())
+        } else {
+            self.name().to_string()
+        }
+    }
+
+    /// Longest output `--length` may ask for, in bytes
+    fn max_output_len(self) -> Option<usize> {
+        match self {
+            Algorithm::Blake2b => Some(blake2b_simd::OUTBYTES),
+            Algorithm::Blake2s => Some(blake2s_simd::OUTBYTES),
+            _ => None,
+        }
+    }
+
+    /// Length of the digest in bytes
+    pub fn output_len(self, options: &Options) -> usize {
+        if self.is_variable_length() {
+            if let Some(bits) = options.length {
+                return bits / 8;
+            }
+        }
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha224 => 28,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha384 => 48,
+            Algorithm::Sha512 => 64,
+            Algorithm::Sha512_224 => 28,
+            Algorithm::Sha512_256 => 32,
+            Algorithm::Sha3_224 => 28,
+            Algorithm::Sha3_256 => 32,
+            Algorithm::Sha3_384 => 48,
+            Algorithm::Sha3_512 => 64,
+            Algorithm::Keccak256 => 32,
+            // Twice the security level, as recommended by FIPS 202
+            Algorithm::Shake128 => 32,
+            Algorithm::Shake256 => 64,
+            Algorithm::Blake2b => 64,
+            Algorithm::Blake2s => 32,
+            Algorithm::Blake3 => 32,
+            Algorithm::Crc(preset) => (preset.width as usize).div_ceil(8),
+            Algorithm::Adler32 => 4,
+            Algorithm::Md5 => 16,
+            Algorithm::Ripemd160 => 20,
+            Algorithm::Whirlpool => 64,
+            Algorithm::Tiger => 24,
+            Algorithm::Streebog256 => 32,
+            Algorithm::Streebog512 => 64,
+            Algorithm::Sm3 => 32,
+            Algorithm::Md4 => 16,
+            Algorithm::Xxh32 => 4,
+            Algorithm::Xxh64 => 8,
+            Algorithm::Xxh3 => 8,
+            Algorithm::Xxh128 => 16,
+            Algorithm::Murmur3_32 => 4,
+            Algorithm::Murmur3_128 => 16,
+            Algorithm::Fnv1a32 => 4,
+            Algorithm::Fnv1a64 => 8,
+            Algorithm::SipHash24 | Algorithm::SipHash13 => 8,
+        }
+    }
+
+    pub fn hasher(self, options: &Options) -> Box<dyn Hasher> {
+        match &options.hmac_key {
+            Some(key) => Box::new(Hmac::new(self, options, key)),
+            None => self.digest_hasher(options),
+        }
+    }
+
+    /// The plain digest, ignoring `hmac_key`
+    fn digest_hasher(self, options: &Options) -> Box<dyn Hasher> {
+        let len = self.output_len(options);
+        match self {
+            Algorithm::Sha1 => Box::new(Sha1::new()),
+            Algorithm::Sha224 => Box::new(Sha224::new()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+            Algorithm::Sha384 => Box::new(Sha384::new()),
+            Algorithm::Sha512 => Box::new(Sha512::new()),
+            Algorithm::Sha512_224 => Box::new(Sha512_224::new()),
+            Algorithm::Sha512_256 => Box::new(Sha512_256::new()),
+            Algorithm::Sha3_224 => Box::new(Sha3_224::new()),
+            Algorithm::Sha3_256 => Box::new(Sha3_256::new()),
+            Algorithm::Sha3_384 => Box::new(Sha3_384::new()),
+            Algorithm::Sha3_512 => Box::new(Sha3_512::new()),
+            Algorithm::Keccak256 => Box::new(Keccak256::new()),
+            Algorithm::Shake128 => Box::new(Xof::new(Shake128::default(), len)),
+            Algorithm::Shake256 => Box::new(Xof::new(Shake256::default(), len)),
+            Algorithm::Blake2b => {
+                let mut params = blake2b_simd::Params::new();
+                params.hash_length(len);
+                if let Some(key) = &options.key {
+                    params.key(&key.0);
+                }
+                Box::new(Blake2b(params.to_state()))
+            }
+            Algorithm::Blake2s => {
+                let mut params = blake2s_simd::Params::new();
+                params.hash_length(len);
+                if let Some(key) = &options.key {
+                    params.key(&key.0);
+                }
+                Box::new(Blake2s(params.to_state()))
+            }
+            Algorithm::Blake3 => Box::new(Blake3::new(options, len)),
+            Algorithm::Crc(preset) => Box::new(checksum::Crc::new(preset)),
+            Algorithm::Adler32 => Box::<checksum::Adler32>::default(),
+            Algorithm::Md5 => Box::new(Md5::new()),
+            Algorithm::Ripemd160 => Box::new(Ripemd160::new()),
+            Algorithm::Whirlpool => Box::new(Whirlpool::new()),
+            Algorithm::Tiger => Box::new(Tiger::new()),
+            Algorithm::Streebog256 => Box::new(Streebog256::new()),
+            Algorithm::Streebog512 => Box::new(Streebog512::new()),
+            Algorithm::Sm3 => Box::new(Sm3::new()),
+            Algorithm::Md4 => Box::new(Md4::new()),
+            Algorithm::Xxh32 => Box::new(fasthash::Xxh32Hasher(Xxh32::new(options.seed as u32))),
+            Algorithm::Xxh64 => Box::new(fasthash::Xxh64Hasher(Xxh64::new(options.seed))),
+            Algorithm::Xxh3 | Algorithm::Xxh128 => Box::new(fasthash::Xxh3Hasher {
+                state: Xxh3::with_seed(options.seed),
+                wide: self == Algorithm::Xxh128,
+            }),
+            Algorithm::Murmur3_32 => Box::new(fasthash::Murmur3_32::new(options.seed as u32)),
+            Algorithm::Murmur3_128 => Box::new(fasthash::Murmur3_128::new(options.seed as u32)),
+            Algorithm::Fnv1a32 => Box::<fasthash::Fnv1a32>::default(),
+            Algorithm::Fnv1a64 => Box::<fasthash::Fnv1a64>::default(),
+            Algorithm::SipHash24 => Box::new(fasthash::SipHash::Sip24(SipHasher24::new_with_key(
+                &sip_key(options),
+            ))),
+            Algorithm::SipHash13 => Box::new(fasthash::SipHash::Sip13(SipHasher13::new_with_key(
+                &sip_key(options),
+            ))),
+        }
+    }
+}
+
+/// The 16-byte SipHash key, all zeroes unless `--key` was given
+fn sip_key(options: &Options) -> [u8; 16] {
+    options.key.as_ref().map_or([0; 16], |k| {
+        k.0[..].try_into().expect("validated key length")
+    })
+}
+
+/// Check that `options` make sense for every one of `algorithms`, and that
+/// each option given is used by at least one of them.
+pub fn validate(algorithms: &[Algorithm], options: &Options) -> Result<(), String> {
+    for &algorithm in algorithms {
+        validate_algorithm(algorithm, options)?;
+    }
+    let uses = |f: fn(Algorithm) -> bool| algorithms.iter().any(|&a| f(a));
+    let blake3 = uses(|a| a == Algorithm::Blake3);
+    let flags = [
+        (
+            "--key",
+            options.key.is_some(),
+            uses(|a| {
+                matches!(
+                    a,
+                    Algorithm::Blake2b
+                        | Algorithm::Blake2s
+                        | Algorithm::Blake3
+                        | Algorithm::SipHash24
+                        | Algorithm::SipHash13
+                )
+            }),
+        ),
+        ("--keyed", options.keyed, blake3),
+        ("--derive-key", options.derive_key.is_some(), blake3),
+        (
+            "--seed",
+            options.seed != 0,
+            uses(|a| {
+                matches!(
+                    a,
+                    Algorithm::Xxh32
+                        | Algorithm::Xxh64
+                        | Algorithm::Xxh3
+                        | Algorithm::Xxh128
+                        | Algorithm::Murmur3_32
+                        | Algorithm::Murmur3_128
+                )
+            }),
+        ),
+        (
+            "--length",
+            options.length.is_some(),
+            uses(Algorithm::is_variable_length),
+        ),
+    ];
+    for (flag, given, applies) in flags {
+        if given && !applies {
+            return Err(format!(
+                "{} does not apply to the selected algorithms",
+                flag
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that `options` make sense for `algorithm` on its own.
+pub fn validate_algorithm(algorithm: Algorithm, options: &Options) -> Result<(), String> {
+    if options.hmac_key.is_some() && algorithm.block_size().is_none() {
+        return Err(format!("HMAC is not defined for {}", algorithm.name()));
+    }
+    let len = algorithm.output_len(options);
+    if len == 0 {
+        return Err(format!(
+            "{} output must be at least 8 bits",
+            algorithm.name()
+        ));
+    }
+    if let Some(max) = algorithm.max_output_len() {
+        if len > max {
+            return Err(format!(
+                "{} output is at most {} bits",
+                algorithm.name(),
+                max * 8
+            ));
+        }
+    }
+    let key_len = options.key.as_ref().map_or(0, |k| k.0.len());
+    match algorithm {
+        Algorithm::Blake2b if key_len > blake2b_simd::KEYBYTES => {
+            return Err(format!(
+                "BLAKE2b keys are at most {} bytes",
+                blake2b_simd::KEYBYTES
+            ));
+        }
+        Algorithm::Blake2s if key_len > blake2s_simd::KEYBYTES => {
+            return Err(format!(
+                "BLAKE2s keys are at most {} bytes",
+                blake2s_simd::KEYBYTES
+            ));
+        }
+        Algorithm::Blake3 if options.key.is_some() && !options.keyed => {
+            return Err("BLAKE3 only uses --key with --keyed".to_string());
+        }
+        Algorithm::Blake3 if options.keyed && key_len != blake3::KEY_LEN => {
+            return Err(format!(
+                "BLAKE3 keyed mode needs a {}-byte key",
+                blake3::KEY_LEN
+            ));
+        }
+        Algorithm::SipHash24 | Algorithm::SipHash13 if options.key.is_some() && key_len != 16 => {
+            return Err("SipHash keys are 16 bytes".to_string());
+        }
+        Algorithm::Xxh32 | Algorithm::Murmur3_32 | Algorithm::Murmur3_128
+            if options.seed > u32::MAX as u64 =>
+        {
+            return Err(format!("{} seeds are 32-bit", algorithm.name()));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Digests produced by a [`MultiHasher`], in selection order
+pub type Digests = Vec<(Algorithm, Vec<u8>)>;
+
+/// An incremental hash function producing a byte string.
+pub trait Hasher: Send {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+impl<D: Digest + Send> Hasher for D {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Digest::finalize(*self).to_vec()
+    }
+}
+
+/// An extendable-output function read out to a fixed length
+struct Xof<X> {
+    xof: X,
+    len: usize,
+}
+
+impl<X> Xof<X> {
+    fn new(xof: X, len: usize) -> Self {
+        Xof { xof, len }
+    }
+}
+
+impl<X: Update + ExtendableOutput + Send> Hasher for Xof<X> {
+    fn update(&mut self, data: &[u8]) {
+        Update::update(&mut self.xof, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.xof.finalize_boxed(self.len).into_vec()
+    }
+}
+
+struct Blake2b(blake2b_simd::State);
+
+impl Hasher for Blake2b {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+struct Blake2s(blake2s_simd::State);
+
+impl Hasher for Blake2s {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+/// HMAC as defined in RFC 2104, over any digest with a block size
+struct Hmac {
+    inner: Box<dyn Hasher>,
+    outer: Box<dyn Hasher>,
+}
+
+impl Hmac {
+    fn new(algorithm: Algorithm, options: &Options, key: &Key) -> Self {
+        let block_size = algorithm.block_size().expect("validated HMAC algorithm");
+        // Keys longer than a block are hashed first, shorter ones zero-padded
+        let mut key = if key.0.len() > block_size {
+            let mut hasher = algorithm.digest_hasher(options);
+            hasher.update(&key.0);
+            hasher.finalize()
+        } else {
+            key.0.clone()
+        };
+        key.resize(block_size, 0);
+
+        let mut inner = algorithm.digest_hasher(options);
+        let mut outer = algorithm.digest_hasher(options);
+        inner.update(&key.iter().map(|b| b ^ 0x36).collect::<Vec<_>>());
+        outer.update(&key.iter().map(|b| b ^ 0x5c).collect::<Vec<_>>());
+        Hmac { inner, outer }
+    }
+}
+
+impl Hasher for Hmac {
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+}
+
+/// Input is gathered into blocks this large before being handed to BLAKE3,
+/// which splits each block across all cores using its tree structure
+const BLAKE3_BLOCK: usize = 1024 * 1024;
+
+struct Blake3 {
+    hasher: blake3::Hasher,
+    buffer: Vec<u8>,
+    len: usize,
+}
+
+impl Blake3 {
+    fn new(options: &Options, len: usize) -> Self {
+        let hasher = if let Some(context) = &options.derive_key {
+            blake3::Hasher::new_derive_key(context)
+        } else if options.keyed {
+            let key = options.key.as_ref().map_or(&[][..], |k| &k.0[..]);
+            blake3::Hasher::new_keyed(key.try_into().expect("validated key length"))
+        } else {
+            blake3::Hasher::new()
+        };
+        Blake3 {
+            hasher,
+            buffer: Vec::with_capacity(BLAKE3_BLOCK),
+            len,
+        }
+    }
+}
+
+impl Hasher for Blake3 {
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = data.len().min(BLAKE3_BLOCK - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() == BLAKE3_BLOCK {
+                self.hasher.update_rayon(&self.buffer);
+                self.buffer.clear();
+            }
+        }
+    }
+
+    fn finalize(mut self: Box<Self>) -> Vec<u8> {
+        self.hasher.update(&self.buffer);
+        let mut output = vec![0u8; self.len];
+        self.hasher.finalize_xof().fill(&mut output);
+        output
+    }
+}
+
+enum Lane {
+    /// Hashed on the calling thread
+    Inline(Box<dyn Hasher>),
+    /// Hashed on a dedicated worker thread fed through a bounded channel
+    Worker {
+        sender: SyncSender<Arc<[u8]>>,
+        handle: JoinHandle<Vec<u8>>,
+    },
+}
+
+/// Feeds each chunk of input to several algorithms at once.
+///
+/// When more than one algorithm is selected every algorithm gets its own
+/// worker thread, so the total time is roughly that of the slowest one.
+pub struct MultiHasher {
+    lanes: Vec<(Algorithm, Lane)>,
+}
+
+impl MultiHasher {
+    pub fn new(algorithms: &[Algorithm], options: &Options) -> Self {
+        Self::with_threads(algorithms, options, algorithms.len() > 1)
+    }
+
+    /// Hash every algorithm on the calling thread, for callers that already
+    /// keep several threads busy with different inputs.
+    pub fn inline(algorithms: &[Algorithm], options: &Options) -> Self {
+        Self::with_threads(algorithms, options, false)
+    }
+
+    fn with_threads(algorithms: &[Algorithm], options: &Options, threaded: bool) -> Self {
+        let lanes = algorithms
+            .iter()
+            .map(|&algorithm| {
+                let mut hasher = algorithm.hasher(options);
+                if !threaded {
+                    return (algorithm, Lane::Inline(hasher));
+                }
+                let (sender, receiver) = mpsc::sync_channel::<Arc<[u8]>>(WORKER_BACKLOG);
+                let handle = thread::spawn(move || {
+                    for chunk in receiver {
+                        hasher.update(&chunk);
+                    }
+                    hasher.finalize()
+                });
+                (algorithm, Lane::Worker { sender, handle })
+            })
+            .collect();
+        MultiHasher { lanes }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        // Only copy the chunk once, however many workers share it
+        let mut shared: Option<Arc<[u8]>> = None;
+        for (_, lane) in &mut self.lanes {
+            match lane {
+                Lane::Inline(hasher) => hasher.update(data),
+                Lane::Worker { sender, .. } => {
+                    let chunk = shared.get_or_insert_with(|| Arc::from(data)).clone();
+                    sender.send(chunk).expect("hash worker exited early");
+                }
+            }
+        }
+    }
+
+    /// Finish every algorithm, returning the digests in selection order.
+    pub fn finalize(self) -> Digests {
+        self.lanes
+            .into_iter()
+            .map(|(algorithm, lane)| {
+                let digest = match lane {
+                    Lane::Inline(hasher) => hasher.finalize(),
+                    Lane::Worker { sender, handle } => {
+                        drop(sender);
+                        handle.join().expect("hash worker panicked")
+                    }
+                };
+                (algorithm, digest)
+            })
+            .collect()
+    }
+}
+
+/// Read `reader` to the end in fixed-size chunks, passing each one to `f`.
+/// Returns the total number of bytes read.
+pub fn for_each_chunk<R: Read>(mut reader: R, mut f: impl FnMut(&[u8])) -> io::Result<u64> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut total = 0u64;
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(total),
+            Ok(n) => {
+                f(&buffer[..n]);
+                total += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Hash everything `reader` produces with each of `algorithms`, returning
+/// the input size and the digests in selection order.
+pub fn hash_reader<R: Read>(
+    reader: R,
+    algorithms: &[Algorithm],
+    options: &Options,
+) -> io::Result<(u64, Digests)> {
+    let mut hasher = MultiHasher::new(algorithms, options);
+    let size = for_each_chunk(reader, |chunk| hasher.update(chunk))?;
+    Ok((size, hasher.finalize()))
+}
+
+/// Hash each of `paths` on `jobs` threads, passing every result to `f`
+/// together with its index. Results arrive in the order of `paths`,
+/// whichever file finishes first.
+pub fn hash_files<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    algorithms: &[Algorithm],
+    options: &Options,
+    jobs: usize,
+    f: impl FnMut(usize, io::Result<(u64, Digests)>) -> io::Result<()>,
+) -> io::Result<()> {
+    hash_file_heads(paths, u64::MAX, algorithms, options, jobs, f)
+}
+
+/// Like [`hash_files`], but only hash the first `limit` bytes of each
+/// file. The size passed to `f` is the number of bytes hashed.
+pub fn hash_file_heads<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    limit: u64,
+    algorithms: &[Algorithm],
+    options: &Options,
+    jobs: usize,
+    mut f: impl FnMut(usize, io::Result<(u64, Digests)>) -> io::Result<()>,
+) -> io::Result<()> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let (sender, next) = (sender.clone(), &next);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                let result = File::open(path).and_then(|file| {
+                    // With several files in flight, don't also fan out
+                    // each file across one thread per algorithm
+                    let mut hasher = if jobs > 1 {
+                        MultiHasher::inline(algorithms, options)
+                    } else {
+                        MultiHasher::new(algorithms, options)
+                    };
+                    let size = for_each_chunk(file.take(limit), |chunk| hasher.update(chunk))?;
+                    Ok((size, hasher.finalize()))
+                });
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut emitted = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&emitted) {
+                f(emitted, result)?;
+                emitted += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC_2202_LONG_DATA: &[u8] =
+        b"Test Using Larger Than Block-Size Key and Larger Than One Block-Size Data";
+    const RFC_4231_LONG_DATA: &[u8] = b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.";
+
+    fn hmac(algorithm: Algorithm, key: &[u8], data: &[u8]) -> String {
+        let options = Options {
+            hmac_key: Some(Key(key.to_vec())),
+            ..Options::default()
+        };
+        let mut hasher = algorithm.hasher(&options);
+        hasher.update(data);
+        hex::encode(hasher.finalize())
+    }
+
+    /// Key and data of the seven test cases of RFC 2202 (MD5 and SHA-1,
+    /// which use different key sizes) and RFC 4231 (SHA-2)
+    fn hmac_inputs(
+        key_len: usize,
+        long_key_len: usize,
+        long_data: &[u8],
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![
+            (vec![0x0b; key_len], b"Hi There".to_vec()),
+            (b"Jefe".to_vec(), b"what do ya want for nothing?".to_vec()),
+            (vec![0xaa; key_len], vec![0xdd; 50]),
+            ((1..=25).collect(), vec![0xcd; 50]),
+            (vec![0x0c; key_len], b"Test With Truncation".to_vec()),
+            (
+                vec![0xaa; long_key_len],
+                b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+            ),
+            (vec![0xaa; long_key_len], long_data.to_vec()),
+        ]
+    }
+
+    #[test]
+    fn hmac_rfc_2202_vectors() {
+        let md5 = [
+            "9294727a3638bb1c13f48ef8158bfc9d",
+            "750c783e6ab0b503eaa86e310a5db738",
+            "56be34521d144c88dbb8c733f0e8b3f6",
+            "697eaf0aca3a3aea3a75164746ffaa79",
+            "56461ef2342edc00f9bab995690efd4c",
+            "6b1ab7fe4bd7bf8f0b62e6ce61b9d0cd",
+            "6f630fad67cda0ee1fb1f562db3aa53e",
+        ];
+        let sha1 = [
+            "b617318655057264e28bc0b6fb378c8ef146be00",
+            "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79",
+            "125d7342b9ac11cd91a39af48aa17b4f63f175d3",
+            "4c9007f4026250c6bc8414f9bf50c86c2d7235da",
+            "4c1a03424b55e07fe7f27be1d58bb9324a9a5a04",
+            "aa4ae5e15272d00e95705637ce8a3b55ed402112",
+            "e8e99d0f45237d786d6bbaa7965c7808bbff1a91",
+        ];
+        for (algorithm, key_len, expected) in
+            [(Algorithm::Md5, 16, md5), (Algorithm::Sha1, 20, sha1)]
+        {
+            for ((key, data), expected) in hmac_inputs(key_len, 80, RFC_2202_LONG_DATA)
+                .iter()
+                .zip(expected)
+            {
+                assert_eq!(hmac(algorithm, key, data), expected, "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn hmac_rfc_4231_vectors() {
+        let expected = [
+            (
+                Algorithm::Sha224,
+                [
+                    "896fb1128abbdf196832107cd49df33f47b4b1169912ba4f53684b22",
+                    "a30e01098bc6dbbf45690f3a7e9e6d0f8bbea2a39e6148008fd05e44",
+                    "7fb3cb3588c6c1f6ffa9694d7d6ad2649365b0c1f65d69d1ec8333ea",
+                    "6c11506874013cac6a2abc1bb382627cec6a90d86efc012de7afec5a",
+                    "0e2aea68a90c8d37c988bcdb9fca6fa8",
+                    "95e9a0db962095adaebe9b2d6f0dbce2d499f112f2d2b7273fa6870e",
+                    "3a854166ac5d9f023f54d517d0b39dbd946770db9c2b95c9f6f565d1",
+                ],
+            ),
+            (
+                Algorithm::Sha256,
+                [
+                    "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                    "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                    "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+                    "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+                    "a3b6167473100ee06e0c796c2955552b",
+                    "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+                    "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+                ],
+            ),
+            (
+                Algorithm::Sha384,
+                [
+                    "afd03944d84895626b0825f4ab46907f15f9dadbe4101ec682aa034c7cebc59cfaea9ea9076ede7f4af152e8b2fa9cb6",
+                    "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e8e2240ca5e69e2c78b3239ecfab21649",
+                    "88062608d3e6ad8a0aa2ace014c8a86f0aa635d947ac9febe83ef4e55966144b2a5ab39dc13814b94e3ab6e101a34f27",
+                    "3e8a69b7783c25851933ab6290af6ca77a9981480850009cc5577c6e1f573b4e6801dd23c4a7d679ccf8a386c674cffb",
+                    "3abf34c3503b2a23a46efc619baef897",
+                    "4ece084485813e9088d2c63a041bc5b44f9ef1012a2b588f3cd11f05033ac4c60c2ef6ab4030fe8296248df163f44952",
+                    "6617178e941f020d351e2f254e8fd32c602420feb0b8fb9adccebb82461e99c5a678cc31e799176d3860e6110c46523e",
+                ],
+            ),
+            (
+                Algorithm::Sha512,
+                [
+                    "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+                    "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+                    "fa73b0089d56a284efb0f0756c890be9b1b5dbdd8ee81a3655f83e33b2279d39bf3e848279a722c806b485a47e67c807b946a337bee8942674278859e13292fb",
+                    "b0ba465637458c6990e5a8c5f61d4af7e576d97ff94b872de76f8050361ee3dba91ca5c11aa25eb4d679275cc5788063a5f19741120c4f2de2adebeb10a298dd",
+                    "415fad6271580a531d4179bc891d87a6",
+                    "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+                    "e37b6a775dc87dbaa4dfa9f96e5e3ffddebd71f8867289865df5a32d20cdc944b6022cac3c4982b10d5eeb55c3e4de15134676fb6de0446065c97440fa8c6a58",
+                ],
+            ),
+        ];
+        for (algorithm, expected) in expected {
+            for ((key, data), expected) in hmac_inputs(20, 131, RFC_4231_LONG_DATA)
+                .iter()
+                .zip(expected)
+            {
+                // Test case 5 is only published truncated to 128 bits
+                let digest = hmac(algorithm, key, data);
+                assert!(digest.starts_with(expected), "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn hmac_sha3_vectors() {
+        // NIST's HMAC-SHA3-256 example for a key shorter than a block
+        let key: Vec<u8> = (0..32).collect();
+        assert_eq!(
+            hmac(
+                Algorithm::Sha3_256,
+                &key,
+                b"Sample message for keylen<blocklen"
+            ),
+            "4fe8e202c4f058e8dddc23d8c34e467343e23555e24fc2f025d598f558f67205"
+        );
+        // Keys longer than every sponge rate, checked against Python's hmac
+        for (algorithm, expected) in [
+            (
+                Algorithm::Sha3_224,
+                "3e96bdd6574eea540b520273b3a8703f8555d5016c1f705e8aad79c3",
+            ),
+            (
+                Algorithm::Sha3_256,
+                "961fcf59ed455732e405e74f5dc78beb7aa41ad315af5e2b2a0dcf8cef9887e0",
+            ),
+            (
+                Algorithm::Sha3_384,
+                "f1d3898bb4cd7b7904fe4e7cecb84d3225b3add0265842ec7386867d662ee33648a90e98a5c673b2266eb1077fb91ed6",
+            ),
+            (
+                Algorithm::Sha3_512,
+                "5576c01b021fc928ae1bc83a6ddb8db5f9a201681e940ecf1f7d5c8a6ec80ac34614d4a68c9581957f69dc8a8c0fbf413984b0f938db3e8212c0744a1a9c5915",
+            ),
+        ] {
+            assert_eq!(hmac(algorithm, &[0xaa; 200], b"abc"), expected);
+        }
+    }
+
+    #[test]
+    fn output_lengths_are_bounded() {
+        let empty = Options {
+            length: Some(0),
+            ..Options::default()
+        };
+        for algorithm in [Algorithm::Blake2b, Algorithm::Blake2s, Algorithm::Shake128] {
+            assert!(validate(&[algorithm], &empty).is_err());
+        }
+        let long = Options {
+            length: Some(8 * (blake2s_simd::OUTBYTES + 1)),
+            ..Options::default()
+        };
+        assert!(validate(&[Algorithm::Blake2s], &long).is_err());
+        assert!(validate(&[Algorithm::Blake2b], &long).is_ok());
+    }
+
+    #[test]
+    fn options_must_apply_to_a_selected_algorithm() {
+        let key = Some(Key(vec![0x11; blake3::KEY_LEN]));
+        let keyed = Options {
+            key: key.clone(),
+            ..Options::default()
+        };
+        assert!(validate(&[Algorithm::Blake3], &keyed).is_err());
+        assert!(validate(&[Algorithm::Sha256], &keyed).is_err());
+        assert!(validate(&[Algorithm::Blake2b, Algorithm::Blake3], &keyed).is_err());
+        assert!(validate(&[Algorithm::Sha256, Algorithm::Blake2b], &keyed).is_ok());
+        let blake3_keyed = Options {
+            key,
+            keyed: true,
+            ..Options::default()
+        };
+        assert!(validate(&[Algorithm::Blake3], &blake3_keyed).is_ok());
+        assert!(validate(&[Algorithm::Blake2s], &blake3_keyed).is_err());
+
+        let seeded = Options {
+            seed: 5,
+            ..Options::default()
+        };
+        assert!(validate(&[Algorithm::Sha256], &seeded).is_err());
+        assert!(validate(&[Algorithm::Sha256, Algorithm::Xxh64], &seeded).is_ok());
+
+        let short = Options {
+            length: Some(64),
+            ..Options::default()
+        };
+        assert!(validate(&[Algorithm::Sha256], &short).is_err());
+        assert!(validate(&[Algorithm::Sha256, Algorithm::Shake128], &short).is_ok());
+    }
+}
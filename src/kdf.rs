@@ -0,0 +1,265 @@
+//! Password hashing for the `kdf` subcommand.
+//!
+//! Argon2, scrypt and PBKDF2 hashes are written as PHC strings
+//! (`$argon2id$v=19$m=...,t=...,p=...$<salt>$<hash>`); bcrypt uses its own
+//! `$2b$<cost>$...` format, which is what every bcrypt implementation reads.
+
+use argon2::Argon2;
+use clap::ValueEnum;
+use colored::*;
+use password_hash::rand_core::{OsRng, RngCore};
+use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+use std::io::{self, Read};
+
+/// Length of generated salts in bytes
+const SALT_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Kdf {
+    Argon2id,
+    Argon2i,
+    Argon2d,
+    Scrypt,
+    Bcrypt,
+    Pbkdf2Sha256,
+    Pbkdf2Sha512,
+}
+
+#[derive(clap::Args)]
+pub struct KdfArgs {
+    /// Password hashing algorithm
+    #[arg(short, long, value_enum, default_value_t = Kdf::Argon2id)]
+    algorithm: Kdf,
+
+    /// Check the password against this encoded hash instead of creating one
+    #[arg(long, value_name = "HASH", conflicts_with_all = [
+        "algorithm", "salt", "salt_hex", "iterations", "memory", "parallelism",
+        "log_n", "block_size", "cost", "output_len",
+    ])]
+    verify: Option<String>,
+
+    /// Salt, used as the bytes of this string (16 random bytes by default)
+    #[arg(long, group = "salt_value")]
+    salt: Option<String>,
+
+    /// Salt given in hex
+    #[arg(long, value_name = "HEX", group = "salt_value", value_parser = parse_hex)]
+    salt_hex: Option<HexSalt>,
+
+    /// Argon2 passes or PBKDF2 rounds (defaults: 2 and 600000)
+    #[arg(long, value_name = "N")]
+    iterations: Option<u32>,
+
+    /// Argon2 memory in KiB (default 19456)
+    #[arg(long, value_name = "KIB")]
+    memory: Option<u32>,
+
+    /// Argon2 lanes or scrypt p (default 1)
+    #[arg(long, value_name = "N")]
+    parallelism: Option<u32>,
+
+    /// scrypt cost as log2(N) (default 17)
+    #[arg(long, value_name = "N")]
+    log_n: Option<u8>,
+
+    /// scrypt block size r (default 8)
+    #[arg(long, value_name = "R")]
+    block_size: Option<u32>,
+
+    /// bcrypt cost as log2 of the number of rounds (default 12)
+    #[arg(long, value_name = "N")]
+    cost: Option<u32>,
+
+    /// Length of the derived hash in bytes (default 32; not for bcrypt)
+    #[arg(long, value_name = "BYTES")]
+    output_len: Option<usize>,
+
+    /// Read the password from stdin instead of prompting for it
+    #[arg(long)]
+    stdin: bool,
+}
+
+#[derive(Clone)]
+struct HexSalt(Vec<u8>);
+
+fn parse_hex(value: &str) -> Result<HexSalt, String> {
+    hex::decode(value)
+        .map(HexSalt)
+        .map_err(|e| format!("invalid hex salt: {}", e))
+}
+
+/// Read the password from stdin (dropping one trailing newline) or from a
+/// prompt on the terminal that does not echo what is typed.
+pub fn read_password(from_stdin: bool) -> io::Result<Vec<u8>> {
+    let mut password = if from_stdin {
+        let mut buffer = Vec::new();
+        io::stdin().read_to_end(&mut buffer)?;
+        buffer
+    } else {
+        rpassword::prompt_password("Password: ")?.into_bytes()
+    };
+    if password.ends_with(b"\n") {
+        password.pop();
+        if password.ends_with(b"\r") {
+            password.pop();
+        }
+    }
+    Ok(password)
+}
+
+impl KdfArgs {
+    /// Reject cost options that `self.algorithm` would silently ignore.
+    fn check_options(&self) -> Result<(), String> {
+        let argon2 = matches!(self.algorithm, Kdf::Argon2id | Kdf::Argon2i | Kdf::Argon2d);
+        let scrypt = self.algorithm == Kdf::Scrypt;
+        let pbkdf2 = matches!(self.algorithm, Kdf::Pbkdf2Sha256 | Kdf::Pbkdf2Sha512);
+        let bcrypt = self.algorithm == Kdf::Bcrypt;
+        let options = [
+            ("--iterations", self.iterations.is_some(), argon2 || pbkdf2),
+            ("--memory", self.memory.is_some(), argon2),
+            (
+                "--parallelism",
+                self.parallelism.is_some(),
+                argon2 || scrypt,
+            ),
+            ("--log-n", self.log_n.is_some(), scrypt),
+            ("--block-size", self.block_size.is_some(), scrypt),
+            ("--cost", self.cost.is_some(), bcrypt),
+            ("--output-len", self.output_len.is_some(), !bcrypt),
+        ];
+        for (option, given, applies) in options {
+            if given && !applies {
+                return Err(format!(
+                    "{} does not apply to {}",
+                    option,
+                    self.algorithm.to_possible_value().unwrap().get_name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn salt(&self) -> Vec<u8> {
+        if let Some(salt) = &self.salt {
+            salt.as_bytes().to_vec()
+        } else if let Some(salt) = &self.salt_hex {
+            salt.0.clone()
+        } else {
+            let mut salt = vec![0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            salt
+        }
+    }
+
+    /// Hash `password`, returning the encoded hash.
+    fn hash(&self, password: &[u8]) -> Result<String, String> {
+        let salt = self.salt();
+        if self.algorithm == Kdf::Bcrypt {
+            let salt: [u8; 16] = salt[..]
+                .try_into()
+                .map_err(|_| "bcrypt salts are 16 bytes".to_string())?;
+            let cost = self.cost.unwrap_or(bcrypt::DEFAULT_COST);
+            return bcrypt::hash_with_salt(password, cost, salt)
+                .map(|parts| parts.format_for_version(bcrypt::Version::TwoB))
+                .map_err(|e| e.to_string());
+        }
+
+        let salt = SaltString::encode_b64(&salt).map_err(|e| format!("invalid salt: {}", e))?;
+        let output_len = self.output_len.unwrap_or(32);
+        let hash = match self.algorithm {
+            Kdf::Argon2id | Kdf::Argon2i | Kdf::Argon2d => {
+                let algorithm = match self.algorithm {
+                    Kdf::Argon2id => argon2::Algorithm::Argon2id,
+                    Kdf::Argon2i => argon2::Algorithm::Argon2i,
+                    _ => argon2::Algorithm::Argon2d,
+                };
+                let params = argon2::Params::new(
+                    self.memory.unwrap_or(argon2::Params::DEFAULT_M_COST),
+                    self.iterations.unwrap_or(argon2::Params::DEFAULT_T_COST),
+                    self.parallelism.unwrap_or(argon2::Params::DEFAULT_P_COST),
+                    Some(output_len),
+                )
+                .map_err(|e| format!("invalid Argon2 parameters: {}", e))?;
+                Argon2::new(algorithm, argon2::Version::V0x13, params)
+                    .hash_password(password, &salt)
+            }
+            Kdf::Scrypt => {
+                let params = scrypt::Params::new(
+                    self.log_n.unwrap_or(scrypt::Params::RECOMMENDED_LOG_N),
+                    self.block_size.unwrap_or(scrypt::Params::RECOMMENDED_R),
+                    self.parallelism.unwrap_or(scrypt::Params::RECOMMENDED_P),
+                    output_len,
+                )
+                .map_err(|e| format!("invalid scrypt parameters: {}", e))?;
+                Scrypt.hash_password_customized(password, None, None, params, &salt)
+            }
+            Kdf::Pbkdf2Sha256 | Kdf::Pbkdf2Sha512 => {
+                let algorithm = if self.algorithm == Kdf::Pbkdf2Sha256 {
+                    pbkdf2::Algorithm::Pbkdf2Sha256
+                } else {
+                    pbkdf2::Algorithm::Pbkdf2Sha512
+                };
+                let params = pbkdf2::Params {
+                    rounds: self
+                        .iterations
+                        .unwrap_or(pbkdf2::Params::RECOMMENDED_ROUNDS as u32),
+                    output_length: output_len,
+                };
+                Pbkdf2.hash_password_customized(
+                    password,
+                    Some(algorithm.ident()),
+                    None,
+                    params,
+                    &salt,
+                )
+            }
+            Kdf::Bcrypt => unreachable!(),
+        };
+        hash.map(|hash| hash.to_string()).map_err(|e| e.to_string())
+    }
+}
+
+/// Check `password` against an encoded hash in any supported format.
+fn verify(encoded: &str, password: &[u8]) -> Result<bool, String> {
+    if encoded.starts_with("$2") {
+        return bcrypt::verify(password, encoded).map_err(|e| e.to_string());
+    }
+    let hash = PasswordHash::new(encoded).map_err(|e| format!("invalid hash: {}", e))?;
+    let verifier: &dyn PasswordVerifier = match hash.algorithm.as_str() {
+        "argon2id" | "argon2i" | "argon2d" => &Argon2::default(),
+        "scrypt" => &Scrypt,
+        "pbkdf2-sha256" | "pbkdf2-sha512" => &Pbkdf2,
+        other => return Err(format!("unsupported algorithm: {}", other)),
+    };
+    match verifier.verify_password(password, &hash) {
+        Ok(()) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Run the `kdf` subcommand. Returns `Ok(false)` if verification failed.
+pub fn run(args: &KdfArgs) -> Result<bool, String> {
+    if args.verify.is_none() {
+        args.check_options()?;
+    }
+    let password = read_password(args.stdin).map_err(|e| format!("cannot read password: {}", e))?;
+
+    match &args.verify {
+        Some(encoded) => {
+            let ok = verify(encoded, &password)?;
+            if ok {
+                println!("{}", "OK".green().bold());
+            } else {
+                println!("{}", "FAILED".red().bold());
+            }
+            Ok(ok)
+        }
+        None => {
+            println!("{}", args.hash(&password)?);
+            Ok(true)
+        }
+    }
+}
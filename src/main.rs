@@ -1,204 +1,893 @@
-use clap::Parser;
+mod check;
+mod checksum;
+mod color;
+mod crypt;
+mod diff;
+mod dupes;
+mod encoding;
+mod fasthash;
+mod hasher;
+mod kdf;
+mod manifest;
+mod output;
+mod sri;
+mod tree;
+mod walk;
+
+use checksum::CrcPreset;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use color::ColorChoice;
 use colored::*;
-use md5::{Digest as Md5Digest, Md5};
-use sha1::Sha1;
-use sha2::{Sha256, Sha512};
-use std::fs;
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use encoding::Encoding;
+use hasher::{Algorithm, Key};
+use output::{Format, Formatter, Source};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use walk::SortOrder;
 
 #[derive(Parser)]
 #[command(name = "shall")]
 #[command(about = "Calculate various hashes of a string or file")]
+#[command(subcommand_negates_reqs = true)]
+#[command(
+    override_usage = "shall [OPTIONS] [INPUT]...\n       shall [--color <WHEN>|--no-color] <COMMAND>"
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Calculate SHA1 hash
     #[arg(long)]
     sha1: bool,
 
+    /// Calculate SHA224 hash
+    #[arg(long)]
+    sha224: bool,
+
     /// Calculate SHA256 hash
     #[arg(long)]
     sha256: bool,
 
+    /// Calculate SHA384 hash
+    #[arg(long)]
+    sha384: bool,
+
     /// Calculate SHA512 hash
     #[arg(long)]
     sha512: bool,
 
+    /// Calculate SHA512/224 hash
+    #[arg(long)]
+    sha512_224: bool,
+
+    /// Calculate SHA512/256 hash
+    #[arg(long)]
+    sha512_256: bool,
+
+    /// Calculate SHA3-224 hash
+    #[arg(long)]
+    sha3_224: bool,
+
+    /// Calculate SHA3-256 hash
+    #[arg(long)]
+    sha3_256: bool,
+
+    /// Calculate SHA3-384 hash
+    #[arg(long)]
+    sha3_384: bool,
+
+    /// Calculate SHA3-512 hash
+    #[arg(long)]
+    sha3_512: bool,
+
+    /// Calculate Keccak-256 hash (original Keccak padding, as used by Ethereum)
+    #[arg(long)]
+    keccak256: bool,
+
+    /// Calculate SHAKE128 output (256 bits unless --length is given)
+    #[arg(long)]
+    shake128: bool,
+
+    /// Calculate SHAKE256 output (512 bits unless --length is given)
+    #[arg(long)]
+    shake256: bool,
+
+    /// Calculate BLAKE2b hash (512 bits unless --length is given)
+    #[arg(long)]
+    blake2b: bool,
+
+    /// Calculate BLAKE2s hash (256 bits unless --length is given)
+    #[arg(long)]
+    blake2s: bool,
+
+    /// Calculate BLAKE3 hash (256 bits unless --length is given)
+    #[arg(long)]
+    blake3: bool,
+
+    /// Key for BLAKE2b/BLAKE2s and SipHash, and for BLAKE3 with --keyed
+    #[arg(long, value_name = "HEX", value_parser = parse_key)]
+    key: Option<Key>,
+
+    /// Use BLAKE3's keyed mode with the 32-byte --key
+    #[arg(long, requires = "key")]
+    keyed: bool,
+
+    /// Use BLAKE3's key derivation mode with this context string
+    #[arg(long, value_name = "CONTEXT", conflicts_with = "keyed")]
+    derive_key: Option<String>,
+
+    /// Calculate CRC-32 checksum (CRC-32/ISO-HDLC, as used by ZIP and gzip)
+    #[arg(long)]
+    crc32: bool,
+
+    /// Calculate CRC-32C checksum (CRC-32/ISCSI, as used by iSCSI and ext4)
+    #[arg(long)]
+    crc32c: bool,
+
+    /// Calculate CRC-64 checksum (CRC-64/XZ)
+    #[arg(long)]
+    crc64: bool,
+
+    /// Calculate a CRC from the catalogue by name, e.g. CRC-16/ARC (repeatable)
+    #[arg(long, value_name = "PRESET", value_parser = parse_crc_preset)]
+    crc: Vec<&'static CrcPreset>,
+
+    /// Calculate Adler-32 checksum
+    #[arg(long)]
+    adler32: bool,
+
+    /// Calculate RIPEMD-160 hash
+    #[arg(long)]
+    ripemd160: bool,
+
+    /// Calculate Whirlpool hash
+    #[arg(long)]
+    whirlpool: bool,
+
+    /// Calculate Tiger hash
+    #[arg(long)]
+    tiger: bool,
+
+    /// Calculate GOST R 34.11-2012 Streebog 256-bit hash
+    #[arg(long)]
+    streebog256: bool,
+
+    /// Calculate GOST R 34.11-2012 Streebog 512-bit hash
+    #[arg(long)]
+    streebog512: bool,
+
+    /// Calculate SM3 hash
+    #[arg(long)]
+    sm3: bool,
+
+    /// Calculate MD4 hash
+    #[arg(long)]
+    md4: bool,
+
+    /// Calculate XXH32 hash
+    #[arg(long)]
+    xxh32: bool,
+
+    /// Calculate XXH64 hash
+    #[arg(long)]
+    xxh64: bool,
+
+    /// Calculate XXH3 (64-bit) hash
+    #[arg(long)]
+    xxh3: bool,
+
+    /// Calculate XXH3 128-bit hash
+    #[arg(long)]
+    xxh128: bool,
+
+    /// Calculate MurmurHash3 x86 32-bit hash
+    #[arg(long)]
+    murmur3_32: bool,
+
+    /// Calculate MurmurHash3 x64 128-bit hash
+    #[arg(long)]
+    murmur3_128: bool,
+
+    /// Calculate FNV-1a 32-bit hash
+    #[arg(long)]
+    fnv1a_32: bool,
+
+    /// Calculate FNV-1a 64-bit hash
+    #[arg(long)]
+    fnv1a_64: bool,
+
+    /// Calculate SipHash-2-4 (keyed with --key, 16 bytes; zero key otherwise)
+    #[arg(long)]
+    siphash_2_4: bool,
+
+    /// Calculate SipHash-1-3 (keyed with --key, 16 bytes; zero key otherwise)
+    #[arg(long)]
+    siphash_1_3: bool,
+
+    /// Seed for xxHash and MurmurHash3
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    seed: u64,
+
+    /// Calculate HMAC-<algorithm> keyed with this string instead of plain digests
+    #[arg(long, value_name = "KEY", group = "hmac", value_parser = parse_hmac_key)]
+    hmac_key: Option<Key>,
+
+    /// Like --hmac-key, using the exact contents of FILE (including any trailing newline)
+    #[arg(long, value_name = "FILE", group = "hmac", value_parser = read_key_file)]
+    hmac_key_file: Option<Key>,
+
+    /// Like --hmac-key, with the key given in hex
+    #[arg(long, value_name = "HEX", group = "hmac", value_parser = parse_key)]
+    hmac_key_hex: Option<Key>,
+
+    /// Output length in bits for variable-length algorithms
+    #[arg(long, value_name = "BITS", value_parser = parse_length)]
+    length: Option<usize>,
+
     /// Calculate MD5 hash
     #[arg(long)]
     md5: bool,
 
-    /// Input file to hash
-    #[arg(long, value_name = "FILE")]
-    file: Option<PathBuf>,
+    /// Input files to hash
+    #[arg(short, long, value_name = "FILE", num_args = 1..)]
+    file: Vec<PathBuf>,
+
+    /// A string to hash; may be repeated
+    #[arg(long, value_name = "STRING")]
+    string: Vec<String>,
 
-    /// Get hashes for all files in a directory (non-recursive)
+    /// Get hashes for all files in a directory
     #[arg(long, value_name = "DIR")]
     directory: Option<PathBuf>,
 
+    /// Number of files to hash at once with --directory (default: number of CPUs)
+    #[arg(short, long, value_name = "N", requires = "directory")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Print one digest covering the whole --directory tree (paths and
+    /// contents) instead of one per file
+    #[arg(long, requires = "directory")]
+    tree_digest: bool,
+
+    /// Include each file's executable bit in --tree-digest
+    #[arg(long, requires = "tree_digest")]
+    tree_exec: bool,
+
+    /// Include symlinks in --tree-digest by their target instead of
+    /// following them
+    #[arg(long, requires = "tree_digest", conflicts_with = "follow_symlinks")]
+    tree_symlinks: bool,
+
+    /// Print the digest of every file and directory in --tree-digest to stderr
+    #[arg(long, requires = "tree_digest")]
+    tree_debug: bool,
+
+    /// Order of files listed with --directory
+    #[arg(long, value_enum, default_value_t = SortOrder::Name, requires = "directory")]
+    sort: SortOrder,
+
+    /// Also hash files in subdirectories of --directory
+    #[arg(long, short, requires = "directory")]
+    recursive: bool,
+
+    /// Only hash files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "directory")]
+    include: Vec<String>,
+
+    /// Skip files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "directory")]
+    exclude: Vec<String>,
+
+    /// Skip files listed in .gitignore and .ignore files
+    #[arg(long, requires = "directory")]
+    gitignore: bool,
+
+    /// Descend into symlinked directories
+    #[arg(long, requires = "directory")]
+    follow_symlinks: bool,
+
+    /// Skip hidden files and directories
+    #[arg(long, requires = "directory")]
+    skip_hidden: bool,
+
+    /// Verify files against a sha256sum-style manifest ("-" for stdin).
+    /// Untagged lines use the selected algorithm matching the digest length,
+    /// or the first algorithm of that length if none is selected
+    #[arg(long, value_name = "MANIFEST")]
+    check: Option<PathBuf>,
+
     /// Read input from stdin
     #[arg(long)]
     stdin: bool,
 
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Shorthand for --format bsd
+    #[arg(long, conflicts_with = "format")]
+    tag: bool,
+
+    /// How digests are written, and read back by --check
+    #[arg(long, value_enum, default_value_t = Encoding::Hex)]
+    encoding: Encoding,
+
+    /// When to use colour
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Shorthand for --color never
+    #[arg(long, global = true, conflicts_with = "color")]
+    no_color: bool,
+
     /// Enable verbose output
     #[arg(long)]
     verbose: bool,
 
-    /// The string to hash (ignored if --file or --stdin is specified)
-    #[arg(required_unless_present_any = ["file", "stdin", "directory"])]
-    input: Option<String>,
+    /// Strings to hash
+    #[arg(required_unless_present_any = ["file", "string", "stdin", "directory", "check"])]
+    input: Vec<String>,
 }
 
-fn print_hash(name: &str, hash: &[u8]) {
-    println!(
-        "{} | {} | {}",
-        name.blue().bold(),
-        "-".cyan(),
-        hex::encode(hash).cyan()
-    );
+#[derive(Subcommand)]
+enum Command {
+    /// Create or verify a password hash with Argon2, scrypt, bcrypt or PBKDF2
+    Kdf(kdf::KdfArgs),
+    /// Create or verify a crypt(3) hash ($1$, $5$ or $6$) as used in /etc/shadow
+    Crypt(crypt::CryptArgs),
+    /// Print Subresource Integrity strings, or check those in an HTML file
+    Sri(sri::SriArgs),
+    /// Compare two manifests and report added, removed, modified and renamed files
+    Diff(diff::DiffArgs),
+    /// Find files with identical contents, and optionally replace them with links
+    Dupes(dupes::DupesArgs),
 }
 
-fn print_file_hash(name: &str, file: &str, hash: &[u8]) {
-    println!(
-        "{} | {} | {}",
-        name.blue().bold(),
-        file.cyan(),
-        hex::encode(hash).cyan()
-    );
+fn parse_length(value: &str) -> Result<usize, String> {
+    let bits: usize = value.parse().map_err(|e| format!("{}", e))?;
+    if bits == 0 || !bits.is_multiple_of(8) {
+        return Err("length must be a positive multiple of 8".to_string());
+    }
+    Ok(bits)
 }
 
-fn calculate_hashes(data: &[u8], args: &Args) {
-    // If no specific algorithm is selected, show all
-    let show_all = !args.sha1 && !args.sha256 && !args.sha512 && !args.md5;
+fn parse_key(value: &str) -> Result<Key, String> {
+    hex::decode(value)
+        .map(Key)
+        .map_err(|e| format!("invalid hex key: {}", e))
+}
 
-    if args.verbose {
-        println!("Input size: {} bytes", data.len());
+fn parse_hmac_key(value: &str) -> Result<Key, String> {
+    Ok(Key(value.as_bytes().to_vec()))
+}
+
+fn read_key_file(value: &str) -> Result<Key, String> {
+    std::fs::read(value)
+        .map(Key)
+        .map_err(|e| format!("cannot read key file: {}", e))
+}
+
+fn parse_crc_preset(value: &str) -> Result<&'static CrcPreset, String> {
+    checksum::preset(value).ok_or_else(|| {
+        let names: Vec<&str> = checksum::PRESETS.iter().map(|p| p.name).collect();
+        format!(
+            "unknown CRC preset; known presets are:\n{}",
+            names.join("\n")
+        )
+    })
+}
+
+fn hash_options(args: &Args) -> hasher::Options {
+    hasher::Options {
+        length: args.length,
+        key: args.key.clone(),
+        keyed: args.keyed,
+        derive_key: args.derive_key.clone(),
+        seed: args.seed,
+        hmac_key: args
+            .hmac_key
+            .clone()
+            .or_else(|| args.hmac_key_file.clone())
+            .or_else(|| args.hmac_key_hex.clone()),
     }
+}
 
-    // Calculate SHA1
-    if show_all || args.sha1 {
-        if args.verbose {
-            print!("Calculating SHA1... ");
-            io::stdout().flush().unwrap();
+/// Algorithms explicitly requested on the command line
+fn explicit_algorithms(args: &Args) -> Vec<Algorithm> {
+    let flags = [
+        (args.sha1, Algorithm::Sha1),
+        (args.sha224, Algorithm::Sha224),
+        (args.sha256, Algorithm::Sha256),
+        (args.sha384, Algorithm::Sha384),
+        (args.sha512, Algorithm::Sha512),
+        (args.sha512_224, Algorithm::Sha512_224),
+        (args.sha512_256, Algorithm::Sha512_256),
+        (args.sha3_224, Algorithm::Sha3_224),
+        (args.sha3_256, Algorithm::Sha3_256),
+        (args.sha3_384, Algorithm::Sha3_384),
+        (args.sha3_512, Algorithm::Sha3_512),
+        (args.keccak256, Algorithm::Keccak256),
+        (args.shake128, Algorithm::Shake128),
+        (args.shake256, Algorithm::Shake256),
+        (args.blake2b, Algorithm::Blake2b),
+        (args.blake2s, Algorithm::Blake2s),
+        (args.blake3, Algorithm::Blake3),
+        (args.crc32, Algorithm::Crc(&checksum::CRC_32)),
+        (args.crc32c, Algorithm::Crc(&checksum::CRC_32C)),
+        (args.crc64, Algorithm::Crc(&checksum::CRC_64)),
+        (args.adler32, Algorithm::Adler32),
+        (args.md5, Algorithm::Md5),
+        (args.ripemd160, Algorithm::Ripemd160),
+        (args.whirlpool, Algorithm::Whirlpool),
+        (args.tiger, Algorithm::Tiger),
+        (args.streebog256, Algorithm::Streebog256),
+        (args.streebog512, Algorithm::Streebog512),
+        (args.sm3, Algorithm::Sm3),
+        (args.md4, Algorithm::Md4),
+        (args.xxh32, Algorithm::Xxh32),
+        (args.xxh64, Algorithm::Xxh64),
+        (args.xxh3, Algorithm::Xxh3),
+        (args.xxh128, Algorithm::Xxh128),
+        (args.murmur3_32, Algorithm::Murmur3_32),
+        (args.murmur3_128, Algorithm::Murmur3_128),
+        (args.fnv1a_32, Algorithm::Fnv1a32),
+        (args.fnv1a_64, Algorithm::Fnv1a64),
+        (args.siphash_2_4, Algorithm::SipHash24),
+        (args.siphash_1_3, Algorithm::SipHash13),
+    ];
+    let mut algorithms: Vec<Algorithm> = flags
+        .into_iter()
+        .filter_map(|(selected, algorithm)| selected.then_some(algorithm))
+        .collect();
+    for &preset in &args.crc {
+        let algorithm = Algorithm::Crc(preset);
+        if !algorithms.contains(&algorithm) {
+            algorithms.push(algorithm);
         }
-        let mut sha1 = Sha1::new();
-        sha1.update(data);
-        print_hash("SHA1    ", &sha1.finalize());
     }
+    algorithms
+}
 
-    // Calculate SHA256
-    if show_all || args.sha256 {
-        if args.verbose {
-            print!("Calculating SHA256... ");
-            io::stdout().flush().unwrap();
-        }
-        let mut sha256 = Sha256::new();
-        sha256.update(data);
-        print_hash("SHA256  ", &sha256.finalize());
+/// Algorithms to calculate; if none are selected, show the default set
+fn selected_algorithms(args: &Args) -> Vec<Algorithm> {
+    let algorithms = explicit_algorithms(args);
+    if algorithms.is_empty() {
+        Algorithm::DEFAULT.to_vec()
+    } else {
+        algorithms
     }
+}
 
-    // Calculate SHA512
-    if show_all || args.sha512 {
-        if args.verbose {
-            print!("Calculating SHA512... ");
-            io::stdout().flush().unwrap();
-        }
-        let mut sha512 = Sha512::new();
-        sha512.update(data);
-        print_hash("SHA512  ", &sha512.finalize());
+fn calculate_hashes<R: Read>(
+    reader: R,
+    source: Source,
+    input: &str,
+    args: &Args,
+    out: &mut dyn Formatter,
+) -> io::Result<()> {
+    let algorithms = selected_algorithms(args);
+    let hash_options = hash_options(args);
+    let hmac = hash_options.hmac_key.is_some();
+
+    if args.verbose {
+        let names: Vec<String> = algorithms.iter().map(|a| a.label(hmac)).collect();
+        eprintln!("Calculating {}...", names.join(", "));
     }
 
-    // Calculate MD5
-    if show_all || args.md5 {
-        if args.verbose {
-            print!("Calculating MD5... ");
-            io::stdout().flush().unwrap();
-        }
-        let mut md5 = Md5::new();
-        md5.update(data);
-        print_hash("MD5     ", &md5.finalize());
+    let (size, digests) = hasher::hash_reader(reader, &algorithms, &hash_options)?;
+
+    if args.verbose {
+        eprintln!("Input size: {} bytes", size);
     }
+
+    for (algorithm, digest) in digests {
+        out.record(&output::Record {
+            source,
+            input,
+            size,
+            algorithm,
+            hmac,
+            digest: &digest,
+        })?;
+    }
+
+    Ok(())
 }
 
-fn process_directory(dir: &PathBuf, args: &Args) -> io::Result<()> {
-    // Ensure exactly one hash type is selected
-    let hash_flags = [args.sha1, args.sha256, args.sha512, args.md5];
-    let selected_count = hash_flags.iter().filter(|&&x| x).count();
+/// Number of threads to hash `count` files with: `--jobs`, or one per CPU
+fn jobs(args: &Args, count: usize) -> usize {
+    let jobs = args.jobs.map_or_else(
+        || std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        NonZeroUsize::get,
+    );
+    jobs.min(count.max(1))
+}
 
-    if selected_count != 1 {
-        eprintln!("Error: When using --directory, exactly one hash type must be selected");
-        std::process::exit(1);
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// `file`'s path relative to `dir` with its names as the file system stores
+/// them, which [`walk::FileEntry::relative`] may have lost.
+fn relative<'a>(dir: &Path, file: &'a walk::FileEntry) -> &'a Path {
+    file.path.strip_prefix(dir).unwrap_or(&file.path)
+}
+
+/// Output one `--tree-digest` per algorithm for `files` (found under
+/// `dir`). Nothing is output if any file can't be read.
+fn process_tree(
+    dir: &Path,
+    files: &[walk::FileEntry],
+    args: &Args,
+    out: &mut dyn Formatter,
+) -> io::Result<bool> {
+    let algorithms = selected_algorithms(args);
+    let hash_options = hash_options(args);
+    let hmac = hash_options.hmac_key.is_some();
+
+    let mut ok = true;
+    let mut report = |out: &mut dyn Formatter, file: &walk::FileEntry, e: io::Error| {
+        ok = false;
+        out.error(Source::File, &file.relative, &e.to_string())
+    };
+
+    let (links, regular): (Vec<&walk::FileEntry>, Vec<&walk::FileEntry>) =
+        files.iter().partition(|f| f.symlink);
+    let mut targets = Vec::new();
+    for link in &links {
+        match fs::read_link(&link.path) {
+            Ok(target) => targets.push(target.into_os_string().into_encoded_bytes()),
+            Err(e) => report(out, link, e)?,
+        }
+    }
+
+    let paths: Vec<&Path> = regular.iter().map(|f| f.path.as_path()).collect();
+    let mut hashed = Vec::new();
+    let mut size = 0;
+    hasher::hash_files(
+        &paths,
+        &algorithms,
+        &hash_options,
+        jobs(args, paths.len()),
+        |index, result| {
+            let file = regular[index];
+            let executable = if args.tree_exec {
+                fs::metadata(&file.path).map(|m| is_executable(&m))
+            } else {
+                Ok(false)
+            };
+            match result.and_then(|(len, digests)| Ok((len, digests, executable?))) {
+                Ok((len, digests, executable)) => {
+                    size += len;
+                    hashed.push((relative(dir, file), digests, executable));
+                    Ok(())
+                }
+                Err(e) => report(out, file, e),
+            }
+        },
+    )?;
+    if !ok {
+        return Ok(false);
+    }
+
+    let dir_name = dir.display().to_string();
+    for (i, &algorithm) in algorithms.iter().enumerate() {
+        let mut leaves: Vec<(&Path, tree::Leaf)> = hashed
+            .iter()
+            .map(|(path, digests, executable)| {
+                let leaf = tree::Leaf::File {
+                    digest: digests[i].1.clone(),
+                    executable: *executable,
+                };
+                (*path, leaf)
+            })
+            .collect();
+        leaves.extend(links.iter().zip(&targets).map(|(link, target)| {
+            let leaf = tree::Leaf::Symlink {
+                target: target.clone(),
+            };
+            (relative(dir, link), leaf)
+        }));
+
+        let digest = match tree::digest(
+            algorithm,
+            &hash_options,
+            &leaves,
+            &mut |kind, path, digest| {
+                if args.tree_debug {
+                    eprintln!(
+                        "{} {:<4} {} {}",
+                        algorithm.label(hmac),
+                        kind,
+                        hex::encode(digest),
+                        path
+                    );
+                }
+            },
+        ) {
+            Ok(digest) => digest,
+            Err(e) => {
+                out.error(Source::Directory, &dir_name, &e)?;
+                return Ok(false);
+            }
+        };
+        out.record(&output::Record {
+            source: Source::Directory,
+            input: &dir_name,
+            size,
+            algorithm,
+            hmac,
+            digest: &digest,
+        })?;
     }
+    Ok(true)
+}
+
+/// Hash every file under `dir`, reporting unreadable files without
+/// stopping. Returns `Ok(false)` if any file could not be hashed.
+fn process_directory(dir: &Path, args: &Args, out: &mut dyn Formatter) -> io::Result<bool> {
+    // Every selected algorithm gets its own record per file, whatever the
+    // output format, so the output can still be filtered by algorithm
+    let algorithms = selected_algorithms(args);
+    let hash_options = hash_options(args);
+    let hmac = hash_options.hmac_key.is_some();
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+    let options = walk::WalkOptions {
+        recursive: args.recursive,
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        gitignore: args.gitignore,
+        follow_symlinks: args.follow_symlinks,
+        skip_hidden: args.skip_hidden,
+        symlinks: args.tree_symlinks,
+    };
 
-        // Skip directories
-        if path.is_dir() {
-            continue;
+    let mut ok = true;
+    let dir_name = dir.display().to_string();
+    let mut files = Vec::new();
+    for entry in walk::walk(dir, &options)? {
+        match entry {
+            Ok(entry) => files.push(entry),
+            Err(e) => {
+                out.error(Source::File, &dir_name, &e.to_string())?;
+                ok = false;
+            }
         }
+    }
+    // Hashing in parallel keeps this order, whichever file finishes first
+    walk::sort(&mut files, args.sort);
 
-        let data = fs::read(&path)?;
-        let file_name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
-
-        if args.sha1 {
-            let mut sha1 = Sha1::new();
-            sha1.update(&data);
-            print_file_hash("SHA1", file_name, &sha1.finalize());
-        } else if args.sha256 {
-            let mut sha256 = Sha256::new();
-            sha256.update(&data);
-            print_file_hash("SHA256", file_name, &sha256.finalize());
-        } else if args.sha512 {
-            let mut sha512 = Sha512::new();
-            sha512.update(&data);
-            print_file_hash("SHA512", file_name, &sha512.finalize());
-        } else if args.md5 {
-            let mut md5 = Md5::new();
-            md5.update(&data);
-            print_file_hash("MD5", file_name, &md5.finalize());
+    if args.tree_digest {
+        // A digest that quietly leaves out part of the tree is worse than
+        // none, so walk errors mean no digest, as read errors do
+        if !ok {
+            return Ok(false);
         }
+        return process_tree(dir, &files, args, out);
     }
-    Ok(())
+
+    let paths: Vec<&Path> = files.iter().map(|f| f.path.as_path()).collect();
+    hasher::hash_files(
+        &paths,
+        &algorithms,
+        &hash_options,
+        jobs(args, paths.len()),
+        |index, result| {
+            let entry = &files[index];
+            match result {
+                Ok((size, digests)) => {
+                    for (algorithm, digest) in digests {
+                        out.record(&output::Record {
+                            source: Source::File,
+                            input: &entry.relative,
+                            size,
+                            algorithm,
+                            hmac,
+                            digest: &digest,
+                        })?;
+                    }
+                }
+                Err(e) => {
+                    out.error(Source::File, &entry.relative, &e.to_string())?;
+                    ok = false;
+                }
+            }
+            Ok(())
+        },
+    )?;
+    Ok(ok)
 }
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
+/// One thing to hash, in command-line order
+enum Input<'a> {
+    Directory(&'a Path),
+    File(&'a Path),
+    Stdin,
+    String(&'a str),
+}
 
-    if let Some(dir) = args.directory.as_ref() {
-        return process_directory(dir, &args);
+/// Every input given on the command line, in the order it was given.
+fn inputs<'a>(args: &'a Args, matches: &ArgMatches) -> Vec<Input<'a>> {
+    let mut inputs: Vec<(usize, Input)> = Vec::new();
+    if let (Some(dir), Some(index)) = (&args.directory, matches.index_of("directory")) {
+        inputs.push((index, Input::Directory(dir)));
+    }
+    if let Some(indices) = matches.indices_of("file") {
+        inputs.extend(indices.zip(&args.file).map(|(i, f)| (i, Input::File(f))));
+    }
+    if let Some(index) = matches.index_of("stdin").filter(|_| args.stdin) {
+        inputs.push((index, Input::Stdin));
     }
+    for (id, strings) in [("string", &args.string), ("input", &args.input)] {
+        if let Some(indices) = matches.indices_of(id) {
+            inputs.extend(indices.zip(strings).map(|(i, s)| (i, Input::String(s))));
+        }
+    }
+    inputs.sort_by_key(|(index, _)| *index);
+    inputs.into_iter().map(|(_, input)| input).collect()
+}
 
-    let data: Vec<u8> = if let Some(file) = args.file.as_ref() {
-        if args.verbose {
-            println!("Reading from file: {}", file.display());
+/// Hash one input, reporting a failure through `out` rather than stopping.
+/// Returns `Ok(false)` if it could not be (completely) hashed.
+fn process_input(input: &Input, args: &Args, out: &mut dyn Formatter) -> io::Result<bool> {
+    match *input {
+        Input::Directory(dir) => match process_directory(dir, args, out) {
+            Ok(ok) => Ok(ok),
+            Err(e) => {
+                out.error(Source::File, &dir.display().to_string(), &e.to_string())?;
+                Ok(false)
+            }
+        },
+        Input::File(file) => {
+            if args.verbose {
+                eprintln!("Reading from file: {}", file.display());
+            }
+            let name = file.display().to_string();
+            let result =
+                File::open(file).and_then(|f| calculate_hashes(f, Source::File, &name, args, out));
+            match result {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    out.error(Source::File, &name, &e.to_string())?;
+                    Ok(false)
+                }
+            }
+        }
+        Input::Stdin => {
+            if args.verbose {
+                eprintln!("Reading from stdin...");
+            }
+            match calculate_hashes(io::stdin().lock(), Source::Stdin, "-", args, out) {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    out.error(Source::Stdin, "-", &e.to_string())?;
+                    Ok(false)
+                }
+            }
+        }
+        Input::String(string) => {
+            calculate_hashes(string.as_bytes(), Source::String, string, args, out)?;
+            Ok(true)
         }
-        match fs::read(file) {
-            Ok(contents) => contents,
+    }
+}
+
+/// Exit with a usage error if `matches` has a subcommand as well as options
+/// that only apply without one. Only the global colour options may come
+/// before a subcommand.
+fn reject_options_before_subcommand(matches: &ArgMatches) {
+    let Some((name, _)) = matches.subcommand() else {
+        return;
+    };
+    let mut command = Args::command();
+    let given = command.get_arguments().find(|arg| {
+        !arg.is_global_set()
+            && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+    });
+    if let Some(arg) = given {
+        let option = match arg.get_long() {
+            Some(long) => format!("--{}", long),
+            None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+        };
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!("{} can't be used with the {} subcommand", option, name),
+            )
+            .exit();
+    }
+}
+
+fn main() -> io::Result<()> {
+    let matches = Args::command().get_matches();
+    reject_options_before_subcommand(&matches);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    color::init(if args.no_color {
+        ColorChoice::Never
+    } else {
+        args.color
+    });
+
+    if let Some(command) = &args.command {
+        let result = match command {
+            Command::Kdf(kdf_args) => kdf::run(kdf_args),
+            Command::Crypt(crypt_args) => crypt::run(crypt_args),
+            Command::Sri(sri_args) => sri::run(sri_args),
+            Command::Diff(diff_args) => diff::run(diff_args),
+            Command::Dupes(dupes_args) => dupes::run(dupes_args),
+        };
+        // Like diff(1) and cmp(1), `diff` exits with 1 when the manifests
+        // differ and 2 when it couldn't compare them
+        let error_code = if matches!(command, Command::Diff(_)) {
+            2
+        } else {
+            1
+        };
+        match result {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
             Err(e) => {
-                eprintln!("{}: {}", "Error reading file".red().bold(), e);
-                std::process::exit(1);
+                eprintln!("{}: {}", color::stderr("Error".red().bold()), e);
+                std::process::exit(error_code);
             }
         }
-    } else if args.stdin {
-        if args.verbose {
-            println!("Reading from stdin...");
+    }
+
+    // Without algorithm flags --check may meet any algorithm, so its options
+    // are only checked against the lines that use them
+    if args.check.is_none() || !explicit_algorithms(&args).is_empty() {
+        if let Err(e) = hasher::validate(&selected_algorithms(&args), &hash_options(&args)) {
+            eprintln!("{}: {}", color::stderr("Error".red().bold()), e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(manifest) = args.check.as_ref() {
+        // Without explicit flags, any algorithm matching the digest length
+        let mut candidates = explicit_algorithms(&args);
+        if candidates.is_empty() {
+            candidates = Algorithm::ALL.to_vec();
         }
-        let mut buffer = Vec::new();
-        match io::stdin().read_to_end(&mut buffer) {
-            Ok(_) => buffer,
+        match check::check_manifest(manifest, &candidates, &hash_options(&args), args.encoding) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
             Err(e) => {
-                eprintln!("{}: {}", "Error reading from stdin".red().bold(), e);
+                eprintln!(
+                    "{}: {}",
+                    color::stderr("Error checking manifest".red().bold()),
+                    e
+                );
                 std::process::exit(1);
             }
         }
-    } else {
-        args.input.as_ref().unwrap().as_bytes().to_vec()
-    };
+    }
 
-    calculate_hashes(&data, &args);
+    let format = if args.tag { Format::Bsd } else { args.format };
+    let mut out = output::formatter(format, args.encoding);
+
+    let inputs = inputs(&args, &matches);
+    let mut ok = true;
+    for input in &inputs {
+        if inputs.len() > 1 {
+            out.begin_input()?;
+        }
+        ok &= process_input(input, &args, out.as_mut())?;
+    }
+
+    out.finish()?;
+    if !ok {
+        std::process::exit(1);
+    }
     Ok(())
 }
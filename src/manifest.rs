@@ -0,0 +1,255 @@
+//! Parsing of checksum manifests as written by `sha256sum` and friends,
+//! and of every other output format shall can write.
+
+use crate::encoding::Encoding;
+use crate::hasher::{Algorithm, Options};
+use serde_json::Value;
+
+/// The CSV header written by `--format csv`
+pub const CSV_HEADER: &str = "input,type,size,algorithm,digest,decimal,error";
+
+/// One line of a manifest
+pub struct Entry {
+    /// Set when the line names its algorithm (all but GNU-style lines)
+    pub algorithm: Option<Algorithm>,
+    /// Set when the line names an `HMAC-` algorithm
+    pub hmac: bool,
+    pub digest: Vec<u8>,
+    pub path: String,
+}
+
+/// Parse a GNU `<hex>  <path>` / `<hex> *<path>` line or a BSD
+/// `NAME (<path>) = <hex>` line.
+///
+/// Lines starting with a backslash have `\\` and `\n` escapes in the path,
+/// as produced by coreutils for names containing newlines or backslashes.
+/// Digests are decoded with `encoding`.
+pub fn parse_line(line: &str, encoding: Encoding) -> Option<Entry> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    let mut entry = parse_bsd(line, encoding).or_else(|| parse_gnu(line, encoding))?;
+    if escaped {
+        entry.path = unescape(&entry.path)?;
+    }
+    Some(entry)
+}
+
+/// Look up an algorithm as shall prints it, returning whether it has an
+/// `HMAC-` prefix.
+fn parse_name(name: &str) -> Option<(Algorithm, bool)> {
+    match name.strip_prefix("HMAC-") {
+        Some(name) => Some((Algorithm::from_name(name)?, true)),
+        None => Some((Algorithm::from_name(name)?, false)),
+    }
+}
+
+fn parse_bsd(line: &str, encoding: Encoding) -> Option<Entry> {
+    let (name, rest) = line.split_once(" (")?;
+    let (algorithm, hmac) = parse_name(name)?;
+    let (path, digest) = rest.rsplit_once(") = ")?;
+    let digest = encoding.decode(digest)?;
+    let expected = algorithm.output_len(&Options::default());
+    if digest.is_empty()
+        || (!algorithm.is_variable_length() && digest.len() != expected)
+        || path.is_empty()
+    {
+        return None;
+    }
+    Some(Entry {
+        algorithm: Some(algorithm),
+        hmac,
+        digest,
+        path: path.to_string(),
+    })
+}
+
+fn parse_gnu(line: &str, encoding: Encoding) -> Option<Entry> {
+    let (digest, rest) = line.split_once(' ')?;
+    let digest = encoding.decode(digest)?;
+    // The second separator is ' ' for text mode and '*' for binary mode
+    let path = rest
+        .strip_prefix(' ')
+        .or_else(|| rest.strip_prefix('*'))
+        .unwrap_or(rest);
+    if digest.is_empty() || path.is_empty() {
+        return None;
+    }
+    Some(Entry {
+        algorithm: None,
+        hmac: false,
+        digest,
+        path: path.to_string(),
+    })
+}
+
+/// Parse a `NAME | input | digest` line of the default table output, where
+/// the digest may be followed by its decimal value in parentheses.
+fn parse_table(line: &str, encoding: Encoding) -> Option<Entry> {
+    let (name, rest) = line.split_once(" | ")?;
+    let (path, digest) = rest.rsplit_once(" | ")?;
+    let (algorithm, hmac) = parse_name(name.trim_end())?;
+    let digest = digest.split(" (").next().unwrap_or(digest);
+    Some(Entry {
+        algorithm: Some(algorithm),
+        hmac,
+        digest: encoding.decode(digest)?,
+        path: path.to_string(),
+    })
+}
+
+/// Build an entry from the fields of a JSON or CSV record, skipping
+/// records that report an error.
+fn parse_record(
+    input: Option<&str>,
+    algorithm: Option<&str>,
+    digest: Option<&str>,
+    encoding: Encoding,
+) -> Option<Entry> {
+    let (algorithm, hmac) = parse_name(algorithm?)?;
+    Some(Entry {
+        algorithm: Some(algorithm),
+        hmac,
+        digest: encoding.decode(digest.filter(|d| !d.is_empty())?)?,
+        path: input?.to_string(),
+    })
+}
+
+fn parse_json_record(value: &Value, encoding: Encoding) -> Option<Entry> {
+    let field = |name: &str| value.get(name).and_then(Value::as_str);
+    parse_record(
+        field("input"),
+        field("algorithm"),
+        field("digest"),
+        encoding,
+    )
+}
+
+/// Split CSV text into records, handling quoted fields with embedded
+/// commas, quotes and line breaks.
+fn csv_records(contents: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            '\r' if !quoted => {}
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// Parse a whole manifest in any format shall writes: the default table,
+/// JSON, NDJSON, CSV, GNU or BSD. Records without a digest (errors,
+/// comments) are skipped, as are lines that can't be parsed.
+pub fn parse_any(contents: &str, encoding: Encoding) -> Result<Vec<Entry>, String> {
+    let trimmed = contents.trim_start();
+    if trimmed.starts_with('[') {
+        let values: Vec<Value> =
+            serde_json::from_str(contents).map_err(|e| format!("invalid JSON: {}", e))?;
+        return Ok(values
+            .iter()
+            .filter_map(|v| parse_json_record(v, encoding))
+            .collect());
+    }
+    if trimmed.starts_with('{') {
+        let mut entries = Vec::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let value: Value =
+                serde_json::from_str(line).map_err(|e| format!("invalid JSON line: {}", e))?;
+            entries.extend(parse_json_record(&value, encoding));
+        }
+        return Ok(entries);
+    }
+    if trimmed.starts_with(CSV_HEADER) {
+        let records = csv_records(trimmed);
+        let header = &records[0];
+        let column = |name: &str| header.iter().position(|h| h == name);
+        let (input, algorithm, digest) = (column("input"), column("algorithm"), column("digest"));
+        let get =
+            |record: &'_ [String], i: Option<usize>| -> Option<String> { record.get(i?).cloned() };
+        return Ok(records[1..]
+            .iter()
+            .filter_map(|r| {
+                parse_record(
+                    get(r, input).as_deref(),
+                    get(r, algorithm).as_deref(),
+                    get(r, digest).as_deref(),
+                    encoding,
+                )
+            })
+            .collect());
+    }
+    Ok(contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| parse_table(line, encoding).or_else(|| parse_line(line, encoding)))
+        .collect())
+}
+
+/// Escape a path the way coreutils does, returning whether any escaping
+/// was needed (in which case the line must start with a backslash).
+pub fn escape(path: &str) -> (bool, String) {
+    if !path.contains(['\\', '\n', '\r']) {
+        return (false, path.to_string());
+    }
+    let escaped = path
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    (true, escaped)
+}
+
+fn unescape(path: &str) -> Option<String> {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bsd_lines_need_a_digest() {
+        for name in ["SHA256", "SHAKE128", "BLAKE2b", "BLAKE3", "HMAC-SHA256"] {
+            let line = format!("{} (a.txt) = ", name);
+            assert!(parse_line(&line, Encoding::Hex).is_none(), "{}", line);
+        }
+        let entry = parse_line("SHAKE128 (a.txt) = 00", Encoding::Hex).unwrap();
+        assert_eq!(entry.algorithm, Some(Algorithm::Shake128));
+        assert_eq!(entry.digest, [0]);
+    }
+}
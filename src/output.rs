@@ -0,0 +1,331 @@
+//! Output formats for hash results.
+
+use crate::color;
+use crate::encoding::Encoding;
+use crate::hasher::Algorithm;
+use crate::manifest;
+use clap::ValueEnum;
+use colored::*;
+use serde_json::{json, Value};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Coloured `NAME | input | hash` lines
+    Table,
+    /// A single JSON array covering the whole run
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma-separated values with a header row
+    Csv,
+    /// `<hash>  <path>`, as printed by sha256sum and friends
+    Gnu,
+    /// `NAME (<path>) = <hash>`, as printed by BSD tools and `--tag`
+    Bsd,
+}
+
+/// Where an input came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    String,
+    Stdin,
+    File,
+    /// A whole directory, for `--tree-digest`
+    Directory,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::String => "string",
+            Source::Stdin => "stdin",
+            Source::File => "file",
+            Source::Directory => "directory",
+        }
+    }
+}
+
+/// The digest of one input under one algorithm
+pub struct Record<'a> {
+    pub source: Source,
+    /// The path for files, the string itself for strings, `-` for stdin
+    pub input: &'a str,
+    pub size: u64,
+    pub algorithm: Algorithm,
+    /// Whether the digest is an HMAC rather than a plain hash
+    pub hmac: bool,
+    pub digest: &'a [u8],
+}
+
+impl Record<'_> {
+    fn name(&self) -> String {
+        self.algorithm.label(self.hmac)
+    }
+
+    /// The digest as a decimal integer, for algorithms whose output is one
+    fn decimal(&self) -> Option<String> {
+        self.algorithm.is_integer().then(|| {
+            let value = self
+                .digest
+                .iter()
+                .fold(0u128, |acc, &byte| (acc << 8) | byte as u128);
+            value.to_string()
+        })
+    }
+}
+
+pub trait Formatter {
+    /// Called before the records of each input when there are several
+    fn begin_input(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn record(&mut self, record: &Record) -> io::Result<()>;
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()>;
+    /// Called once after the last record or error
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn formatter(format: Format, encoding: Encoding) -> Box<dyn Formatter> {
+    match format {
+        Format::Table => Box::new(Table {
+            encoding,
+            blocks: 0,
+        }),
+        Format::Json => Box::new(Json {
+            encoding,
+            values: Vec::new(),
+        }),
+        Format::Ndjson => Box::new(Ndjson { encoding }),
+        Format::Csv => Box::new(Csv {
+            encoding,
+            header_written: false,
+        }),
+        Format::Gnu => Box::new(Gnu { encoding }),
+        Format::Bsd => Box::new(Bsd { encoding }),
+    }
+}
+
+struct Table {
+    encoding: Encoding,
+    /// Number of input blocks started; with more than one input, strings
+    /// are shown instead of `-` and blocks are separated by blank lines
+    blocks: usize,
+}
+
+impl Formatter for Table {
+    fn begin_input(&mut self) -> io::Result<()> {
+        if self.blocks > 0 {
+            writeln!(io::stdout())?;
+        }
+        self.blocks += 1;
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        let (name, input) = match record.source {
+            Source::File | Source::Directory => (record.name(), record.input.to_string()),
+            Source::String if self.blocks > 0 => (
+                format!("{:<8}", record.name()),
+                coreutils_label(record.source, record.input),
+            ),
+            Source::String | Source::Stdin => (format!("{:<8}", record.name()), "-".to_string()),
+        };
+        let mut digest = self.encoding.encode(record.digest);
+        if let Some(decimal) = record.decimal() {
+            digest = format!("{} ({})", digest, decimal);
+        }
+        writeln!(
+            io::stdout(),
+            "{} | {} | {}",
+            name.blue().bold(),
+            input.cyan(),
+            digest.cyan()
+        )
+    }
+
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()> {
+        let context = match source {
+            Source::Stdin => "Error reading from stdin".to_string(),
+            Source::String | Source::File | Source::Directory => {
+                format!("Error reading {}", input)
+            }
+        };
+        eprintln!("{}: {}", color::stderr(context.red().bold()), message);
+        Ok(())
+    }
+}
+
+fn record_value(record: &Record, encoding: Encoding) -> Value {
+    let mut value = json!({
+        "input": record.input,
+        "type": record.source.name(),
+        "size": record.size,
+        "algorithm": record.name(),
+        "digest": encoding.encode(record.digest),
+    });
+    // A string, since 128-bit values don't survive JSON number parsing
+    if let Some(decimal) = record.decimal() {
+        value["decimal"] = Value::String(decimal);
+    }
+    value
+}
+
+fn error_value(source: Source, input: &str, message: &str) -> Value {
+    json!({
+        "input": input,
+        "type": source.name(),
+        "error": message,
+    })
+}
+
+struct Json {
+    encoding: Encoding,
+    values: Vec<Value>,
+}
+
+impl Formatter for Json {
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        self.values.push(record_value(record, self.encoding));
+        Ok(())
+    }
+
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()> {
+        self.values.push(error_value(source, input, message));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer_pretty(&mut stdout, &self.values)?;
+        writeln!(stdout)
+    }
+}
+
+struct Ndjson {
+    encoding: Encoding,
+}
+
+impl Formatter for Ndjson {
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        writeln!(io::stdout(), "{}", record_value(record, self.encoding))
+    }
+
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()> {
+        writeln!(io::stdout(), "{}", error_value(source, input, message))
+    }
+}
+
+struct Csv {
+    encoding: Encoding,
+    header_written: bool,
+}
+
+impl Csv {
+    fn header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(io::stdout(), "{}", manifest::CSV_HEADER)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn row(&mut self, fields: [&str; 7]) -> io::Result<()> {
+        self.header()?;
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        writeln!(io::stdout(), "{}", fields.join(","))
+    }
+}
+
+/// Quote a field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl Formatter for Csv {
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        self.row([
+            record.input,
+            record.source.name(),
+            &record.size.to_string(),
+            &record.name(),
+            &self.encoding.encode(record.digest),
+            &record.decimal().unwrap_or_default(),
+            "",
+        ])
+    }
+
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()> {
+        self.row([input, source.name(), "", "", "", "", message])
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // An empty run still produces a parseable file
+        self.header()
+    }
+}
+
+/// The name coreutils-style formats print for an input: the path for
+/// files, `-` for stdin and the quoted string for strings (like `md5 -s`).
+fn coreutils_label(source: Source, input: &str) -> String {
+    match source {
+        Source::File | Source::Directory => input.to_string(),
+        Source::Stdin => "-".to_string(),
+        Source::String => format!("\"{}\"", input),
+    }
+}
+
+fn coreutils_error(source: Source, input: &str, message: &str) -> io::Result<()> {
+    let label = coreutils_label(source, input);
+    eprintln!("shall: {}: {}", label, message);
+    Ok(())
+}
+
+struct Gnu {
+    encoding: Encoding,
+}
+
+impl Formatter for Gnu {
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        let (escaped, label) = manifest::escape(&coreutils_label(record.source, record.input));
+        writeln!(
+            io::stdout(),
+            "{}{}  {}",
+            if escaped { "\\" } else { "" },
+            self.encoding.encode(record.digest),
+            label
+        )
+    }
+
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()> {
+        coreutils_error(source, input, message)
+    }
+}
+
+struct Bsd {
+    encoding: Encoding,
+}
+
+impl Formatter for Bsd {
+    fn record(&mut self, record: &Record) -> io::Result<()> {
+        let (escaped, label) = manifest::escape(&coreutils_label(record.source, record.input));
+        writeln!(
+            io::stdout(),
+            "{}{} ({}) = {}",
+            if escaped { "\\" } else { "" },
+            record.name(),
+            label,
+            self.encoding.encode(record.digest)
+        )
+    }
+
+    fn error(&mut self, source: Source, input: &str, message: &str) -> io::Result<()> {
+        coreutils_error(source, input, message)
+    }
+}
@@ -0,0 +1,324 @@
+//! Subresource Integrity strings for the `sri` subcommand, and checking
+//! the `integrity` attributes of an HTML page against local files.
+
+use crate::color;
+use crate::encoding::Encoding;
+use crate::hasher::{self, Algorithm, Options};
+use clap::ValueEnum;
+use colored::*;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SriAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl SriAlgorithm {
+    fn algorithm(self) -> Algorithm {
+        match self {
+            SriAlgorithm::Sha256 => Algorithm::Sha256,
+            SriAlgorithm::Sha384 => Algorithm::Sha384,
+            SriAlgorithm::Sha512 => Algorithm::Sha512,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            SriAlgorithm::Sha256 => "sha256",
+            SriAlgorithm::Sha384 => "sha384",
+            SriAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<SriAlgorithm> {
+        [
+            SriAlgorithm::Sha256,
+            SriAlgorithm::Sha384,
+            SriAlgorithm::Sha512,
+        ]
+        .into_iter()
+        .find(|a| a.prefix() == prefix)
+    }
+}
+
+#[derive(clap::Args)]
+pub struct SriArgs {
+    /// Files to print integrity strings for
+    #[arg(required_unless_present = "check_html")]
+    files: Vec<PathBuf>,
+
+    /// Hash algorithm; repeat to put several hashes in one integrity string
+    #[arg(
+        short,
+        long = "algorithm",
+        value_enum,
+        default_values_t = [SriAlgorithm::Sha384]
+    )]
+    algorithms: Vec<SriAlgorithm>,
+
+    /// Check the integrity attributes of the <script> and <link> tags in
+    /// this HTML file against the local files they reference
+    #[arg(long, value_name = "HTML", conflicts_with_all = ["files", "algorithms"])]
+    check_html: Option<PathBuf>,
+
+    /// Directory that paths starting with `/` are relative to (by default
+    /// the HTML file's directory)
+    #[arg(long, value_name = "DIR", requires = "check_html")]
+    root: Option<PathBuf>,
+}
+
+/// Hash `path` with each of `algorithms`, returning `(algorithm, base64)`
+/// pairs in the same order.
+fn digests(path: &Path, algorithms: &[SriAlgorithm]) -> io::Result<Vec<(SriAlgorithm, String)>> {
+    let selected: Vec<Algorithm> = algorithms.iter().map(|a| a.algorithm()).collect();
+    let (_, digests) = hasher::hash_reader(File::open(path)?, &selected, &Options::default())?;
+    Ok(algorithms
+        .iter()
+        .zip(digests)
+        .map(|(&sri, (_, digest))| (sri, Encoding::Base64.encode(&digest)))
+        .collect())
+}
+
+fn generate(args: &SriArgs) -> bool {
+    let mut algorithms: Vec<SriAlgorithm> = Vec::new();
+    for &algorithm in &args.algorithms {
+        if !algorithms.contains(&algorithm) {
+            algorithms.push(algorithm);
+        }
+    }
+    let mut ok = true;
+    for file in &args.files {
+        match digests(file, &algorithms) {
+            Ok(digests) => {
+                let integrity: Vec<String> = digests
+                    .iter()
+                    .map(|(sri, digest)| format!("{}-{}", sri.prefix(), digest))
+                    .collect();
+                println!("{}  {}", integrity.join(" "), file.display());
+            }
+            Err(e) => {
+                let context = format!("Error reading {}", file.display());
+                eprintln!("{}: {}", color::stderr(context.red().bold()), e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// A `<script>` or `<link>` tag carrying an `integrity` attribute
+struct Tag {
+    /// The `src` or `href` attribute
+    url: String,
+    integrity: String,
+}
+
+/// Parse the attributes of a tag, starting just after its name. Returns the
+/// attributes (names lowercased) and the length of text consumed.
+fn parse_attributes(text: &str) -> (Vec<(String, String)>, usize) {
+    let bytes = text.as_bytes();
+    let mut attributes = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            return (attributes, (i + 1).min(bytes.len()));
+        }
+        let start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !b"=>/".contains(&bytes[i]) {
+            i += 1;
+        }
+        let name = text[start..i].to_ascii_lowercase();
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut value = String::new();
+        if i < bytes.len() && bytes[i] == b'=' {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if i < bytes.len() && (bytes[i] == b'"' || bytes[i] == b'\'') {
+                let quote = bytes[i];
+                let start = i + 1;
+                i = start;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+                value = text[start..i].to_string();
+                i = (i + 1).min(bytes.len());
+            } else {
+                let start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+                value = text[start..i].to_string();
+            }
+        }
+        attributes.push((name, value));
+    }
+}
+
+/// Find the `<script src>` and `<link href>` tags in `html` that have an
+/// `integrity` attribute, skipping comments.
+fn find_tags(html: &str) -> Vec<Tag> {
+    let lower = html.to_ascii_lowercase();
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = lower[pos..].find('<') {
+        let start = pos + offset + 1;
+        if lower[start..].starts_with("!--") {
+            pos = lower[start..]
+                .find("-->")
+                .map_or(lower.len(), |end| start + end + 3);
+            continue;
+        }
+        let name_len = lower[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(lower.len() - start);
+        let name = &lower[start..start + name_len];
+        let (attributes, consumed) = parse_attributes(&html[start + name_len..]);
+        pos = start + name_len + consumed;
+
+        let url_attribute = match name {
+            "script" => "src",
+            "link" => "href",
+            _ => continue,
+        };
+        let get = |wanted: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == wanted)
+                .map(|(_, value)| value.clone())
+        };
+        if let (Some(url), Some(integrity)) = (get(url_attribute), get("integrity")) {
+            tags.push(Tag { url, integrity });
+        }
+    }
+    tags
+}
+
+/// Map a URL from the page to a local file, or `None` for remote URLs.
+fn local_path(url: &str, html_dir: &Path, root: &Path) -> Option<PathBuf> {
+    if url.starts_with("//") || url.contains("://") || url.starts_with("data:") {
+        return None;
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let path = percent_decode(path);
+    Some(match path.strip_prefix('/') {
+        Some(rest) => root.join(rest),
+        None => html_dir.join(path),
+    })
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The hashes in an integrity attribute that use its strongest algorithm,
+/// which are the only ones a browser checks.
+fn strongest(integrity: &str) -> Option<(SriAlgorithm, Vec<String>)> {
+    let hashes: Vec<(SriAlgorithm, String)> = integrity
+        .split_whitespace()
+        .filter_map(|token| {
+            // Anything after `?` is reserved for options
+            let token = token.split('?').next().unwrap_or(token);
+            let (prefix, digest) = token.split_once('-')?;
+            Some((SriAlgorithm::from_prefix(prefix)?, digest.to_string()))
+        })
+        .collect();
+    let best = hashes.iter().map(|(sri, _)| *sri).max()?;
+    let digests = hashes
+        .into_iter()
+        .filter(|(sri, _)| *sri == best)
+        .map(|(_, digest)| digest)
+        .collect();
+    Some((best, digests))
+}
+
+fn check_html(html_path: &Path, root: Option<&Path>) -> Result<bool, String> {
+    let html = fs::read_to_string(html_path)
+        .map_err(|e| format!("cannot read {}: {}", html_path.display(), e))?;
+    let html_dir = html_path.parent().unwrap_or(Path::new(""));
+    let root = root.unwrap_or(html_dir);
+
+    let tags = find_tags(&html);
+    if tags.is_empty() {
+        return Err("no tags with an integrity attribute found".to_string());
+    }
+
+    let mut failed = 0;
+    let mut missing = 0;
+    for tag in &tags {
+        let Some(path) = local_path(&tag.url, html_dir, root) else {
+            println!("{}: {}", tag.url, "SKIPPED (remote)".yellow());
+            continue;
+        };
+        let Some((algorithm, expected)) = strongest(&tag.integrity) else {
+            println!("{}: {} (no usable hash)", tag.url, "FAILED".red().bold());
+            failed += 1;
+            continue;
+        };
+        match digests(&path, &[algorithm]) {
+            Ok(actual) if expected.contains(&actual[0].1) => {
+                println!("{}: {}", tag.url, "OK".green().bold());
+            }
+            Ok(_) => {
+                println!("{}: {}", tag.url, "FAILED".red().bold());
+                failed += 1;
+            }
+            Err(_) => {
+                println!("{}: {}", tag.url, "MISSING".yellow().bold());
+                missing += 1;
+            }
+        }
+    }
+
+    if missing > 0 {
+        eprintln!(
+            "{}: {} referenced file(s) could not be read",
+            color::stderr("Warning".yellow().bold()),
+            missing
+        );
+    }
+    if failed > 0 {
+        eprintln!(
+            "{}: {} integrity attribute(s) did NOT match",
+            color::stderr("Warning".yellow().bold()),
+            failed
+        );
+    }
+    Ok(failed == 0 && missing == 0)
+}
+
+/// Run the `sri` subcommand. Returns `Ok(false)` if a file could not be
+/// read or an integrity attribute did not match.
+pub fn run(args: &SriArgs) -> Result<bool, String> {
+    match &args.check_html {
+        Some(html) => check_html(html, args.root.as_deref()),
+        None => Ok(generate(args)),
+    }
+}
@@ -0,0 +1,175 @@
+//! A single Merkle digest for a whole directory tree (`--tree-digest`).
+//!
+//! Every node is hashed with the selected algorithm `H`, and `||` below
+//! means concatenation:
+//!
+//! ```text
+//! file:      H("file" || 0x00 || mode || 0x00 || H(contents))
+//! symlink:   H("link" || 0x00 || target)
+//! directory: H("dir" || 0x00 || name_1 || 0x00 || node_1 || name_2 || 0x00 || node_2 ...)
+//! ```
+//!
+//! `mode` is `x` for an executable file when `--tree-exec` is given and `-`
+//! otherwise. Symlinks only appear with `--tree-symlinks`; `target` is the
+//! link's target as stored. Directory entries are sorted byte-wise by name,
+//! names are the raw bytes the file system stores (WTF-8 on Windows) and
+//! every node digest has the algorithm's fixed length, so the encoding is
+//! unambiguous. The tree digest is the node digest of the root directory.
+//! Directories without any files are not represented.
+
+use crate::hasher::{Algorithm, Options};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A file or symlink in the tree
+pub enum Leaf {
+    File { digest: Vec<u8>, executable: bool },
+    Symlink { target: Vec<u8> },
+}
+
+enum Node {
+    Leaf(Vec<u8>),
+    Dir(BTreeMap<Vec<u8>, Node>),
+}
+
+fn hash(algorithm: Algorithm, options: &Options, parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = algorithm.hasher(options);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+/// Render a path for `debug`, which only shows it to people
+fn display(names: &[&[u8]]) -> String {
+    names
+        .iter()
+        .map(|name| String::from_utf8_lossy(name))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Calculate the tree digest of `leaves`, keyed by their path relative to
+/// the root. `debug` is called with the kind, path and digest of every
+/// node, children before their parents.
+pub fn digest(
+    algorithm: Algorithm,
+    options: &Options,
+    leaves: &[(&Path, Leaf)],
+    debug: &mut dyn FnMut(&str, &str, &[u8]),
+) -> Result<Vec<u8>, String> {
+    let mut root = BTreeMap::new();
+    for (path, leaf) in leaves {
+        let (kind, node) = match leaf {
+            Leaf::File { digest, executable } => {
+                let mode: &[u8] = if *executable { b"x" } else { b"-" };
+                (
+                    "file",
+                    hash(algorithm, options, &[b"file\0", mode, b"\0", digest]),
+                )
+            }
+            Leaf::Symlink { target } => ("link", hash(algorithm, options, &[b"link\0", target])),
+        };
+        let names: Vec<&[u8]> = path
+            .components()
+            .map(|c| c.as_os_str().as_encoded_bytes())
+            .collect();
+        debug(kind, &display(&names), &node);
+
+        let Some((name, parents)) = names.split_last() else {
+            return Err("a leaf has an empty path".to_string());
+        };
+        let mut dir = &mut root;
+        for (depth, parent) in parents.iter().enumerate() {
+            let child = dir
+                .entry(parent.to_vec())
+                .or_insert_with(|| Node::Dir(BTreeMap::new()));
+            dir = match child {
+                Node::Dir(entries) => entries,
+                Node::Leaf(_) => {
+                    return Err(format!(
+                        "{} is both a file and a directory",
+                        display(&names[..=depth])
+                    ))
+                }
+            };
+        }
+        if dir.insert(name.to_vec(), Node::Leaf(node)).is_some() {
+            return Err(format!("{} appears twice in the tree", display(&names)));
+        }
+    }
+    Ok(dir_digest(algorithm, options, &root, ".", debug))
+}
+
+fn dir_digest(
+    algorithm: Algorithm,
+    options: &Options,
+    entries: &BTreeMap<Vec<u8>, Node>,
+    path: &str,
+    debug: &mut dyn FnMut(&str, &str, &[u8]),
+) -> Vec<u8> {
+    let mut encoded = b"dir\0".to_vec();
+    for (name, node) in entries {
+        let digest = match node {
+            Node::Leaf(digest) => digest.clone(),
+            Node::Dir(children) => {
+                let name = String::from_utf8_lossy(name);
+                let child_path = if path == "." {
+                    name.into_owned()
+                } else {
+                    format!("{}/{}", path, name)
+                };
+                dir_digest(algorithm, options, children, &child_path, debug)
+            }
+        };
+        encoded.extend_from_slice(name);
+        encoded.push(0);
+        encoded.extend_from_slice(&digest);
+    }
+    let digest = hash(algorithm, options, &[&encoded]);
+    debug("dir", path, &digest);
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(contents: &[u8]) -> Leaf {
+        Leaf::File {
+            digest: contents.to_vec(),
+            executable: false,
+        }
+    }
+
+    fn tree_digest(leaves: &[(&Path, Leaf)]) -> Result<Vec<u8>, String> {
+        digest(
+            Algorithm::Sha256,
+            &Options::default(),
+            leaves,
+            &mut |_, _, _| {},
+        )
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn names_that_are_not_utf8_stay_distinct() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let ff = Path::new(OsStr::from_bytes(b"a\xff"));
+        let fe = Path::new(OsStr::from_bytes(b"a\xfe"));
+        let both = tree_digest(&[(ff, file(b"1")), (fe, file(b"2"))]).unwrap();
+        let one = tree_digest(&[(ff, file(b"1"))]).unwrap();
+        assert_ne!(both, one);
+
+        let nested = Path::new(OsStr::from_bytes(b"a\xff/x"));
+        assert!(tree_digest(&[(nested, file(b"1")), (fe, file(b"2"))]).is_ok());
+    }
+
+    #[test]
+    fn a_file_inside_a_file_is_an_error() {
+        let leaves = [(Path::new("a"), file(b"1")), (Path::new("a/b"), file(b"2"))];
+        assert!(tree_digest(&leaves).is_err());
+    }
+}
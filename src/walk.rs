@@ -0,0 +1,140 @@
+//! Directory traversal for `--directory`.
+
+use clap::ValueEnum;
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Controls which files a directory walk yields
+pub struct WalkOptions {
+    /// Descend into subdirectories
+    pub recursive: bool,
+    /// Only yield files matching at least one of these globs (if any)
+    pub include: Vec<String>,
+    /// Never yield files matching these globs
+    pub exclude: Vec<String>,
+    /// Honour `.gitignore` and `.ignore` files
+    pub gitignore: bool,
+    /// Descend into symlinked directories
+    pub follow_symlinks: bool,
+    /// Skip files and directories whose name starts with a dot
+    pub skip_hidden: bool,
+    /// Yield symlinks themselves, whatever they point to
+    pub symlinks: bool,
+}
+
+/// Order in which directory entries are listed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Byte-wise by relative path, with `/` as separator
+    Name,
+    /// Smallest first, then by name
+    Size,
+    /// Least recently modified first, then by name
+    Mtime,
+    /// As the file system returns them
+    None,
+}
+
+/// A regular file found by [`walk`]
+pub struct FileEntry {
+    pub path: PathBuf,
+    /// Path relative to the walk root, always using `/` as separator
+    pub relative: String,
+    /// Set for symlinks yielded because of [`WalkOptions::symlinks`]
+    pub symlink: bool,
+}
+
+/// Render `path` relative to `root` with `/` separators.
+pub fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Walk `root`, yielding every regular file (or symlink to one) that passes
+/// the filters in `options`. With `options.symlinks`, every symlink is
+/// yielded as such instead.
+pub fn walk(
+    root: &Path,
+    options: &WalkOptions,
+) -> io::Result<impl Iterator<Item = io::Result<FileEntry>>> {
+    if !root.metadata()?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            "not a directory",
+        ));
+    }
+
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in &options.include {
+        overrides.add(glob).map_err(io::Error::other)?;
+    }
+    for glob in &options.exclude {
+        overrides
+            .add(&format!("!{}", glob))
+            .map_err(io::Error::other)?;
+    }
+    let overrides = overrides.build().map_err(io::Error::other)?;
+
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(options.skip_hidden)
+        .ignore(options.gitignore)
+        .git_ignore(options.gitignore)
+        .git_exclude(options.gitignore)
+        .require_git(false)
+        .follow_links(options.follow_symlinks)
+        .max_depth(if options.recursive { None } else { Some(1) })
+        .overrides(overrides)
+        .build();
+
+    let root = root.to_path_buf();
+    let symlinks = options.symlinks;
+    Ok(walker.filter_map(move |entry| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(io::Error::other(e))),
+        };
+        let symlink = symlinks && entry.path_is_symlink();
+        // Skip directories, broken symlinks and special files
+        if !symlink && !entry.path().is_file() {
+            return None;
+        }
+        Some(Ok(FileEntry {
+            relative: relative_path(&root, entry.path()),
+            path: entry.into_path(),
+            symlink,
+        }))
+    }))
+}
+
+/// Sort `files` into `order`. Files whose metadata can't be read sort as if
+/// empty and unmodified since the epoch; the read error shows up when they
+/// are hashed.
+pub fn sort(files: &mut [FileEntry], order: SortOrder) {
+    // Sorting by name first makes ties in size or mtime come out the same
+    // on every platform
+    if order != SortOrder::None {
+        files.sort_by(|a, b| a.relative.cmp(&b.relative));
+    }
+    match order {
+        SortOrder::Size => {
+            files.sort_by_cached_key(|f| fs::metadata(&f.path).map_or(0, |m| m.len()));
+        }
+        SortOrder::Mtime => {
+            files.sort_by_cached_key(|f| {
+                fs::metadata(&f.path)
+                    .and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH)
+            });
+        }
+        SortOrder::Name | SortOrder::None => {}
+    }
+}